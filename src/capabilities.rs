@@ -0,0 +1,119 @@
+//! What a `rumdl` binary supports, probed once per binary instead of once per
+//! feature that depends on it.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use zed_extension_api as zed;
+
+use crate::version;
+
+#[derive(Clone)]
+pub struct Capabilities {
+    /// The version `rumdl --version` reported, if it could be parsed.
+    pub version: Option<String>,
+    /// Subcommands listed by `rumdl --help`.
+    subcommands: Vec<String>,
+}
+
+impl Capabilities {
+    pub fn supports(&self, subcommand: &str) -> bool {
+        self.subcommands.iter().any(|name| name == subcommand)
+    }
+}
+
+/// Probe results keyed by binary path, invalidated when the file's
+/// modification time changes.
+pub struct CapabilityCache {
+    entries: BTreeMap<PathBuf, (Option<SystemTime>, Capabilities)>,
+}
+
+impl CapabilityCache {
+    pub const fn new() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+        }
+    }
+
+    /// Returns what `binary` supports, or `None` if it couldn't be run at all.
+    pub fn get(&mut self, binary: &Path, env: &[(String, String)]) -> Option<Capabilities> {
+        self.try_get(binary, env)
+            .inspect_err(|e| eprintln!("[rumdl] failed to probe {}: {e}", binary.display()))
+            .ok()
+    }
+
+    /// Like [`Self::get`], but leaves reporting a binary that can't be run to
+    /// the caller, e.g. when probing for one that may not exist.
+    pub fn try_get(
+        &mut self,
+        binary: &Path,
+        env: &[(String, String)],
+    ) -> zed::Result<Capabilities> {
+        // Binaries outside the extension directory can't be stat'ed from the
+        // sandbox, so those are only probed once per session.
+        let modified = fs::metadata(binary)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        if let Some((probed_modified, capabilities)) = self.entries.get(binary)
+            && *probed_modified == modified
+        {
+            return Ok(capabilities.clone());
+        }
+
+        let capabilities = probe(binary, env)?;
+        eprintln!(
+            "[rumdl] probed {}: version {}",
+            binary.display(),
+            capabilities.version.as_deref().unwrap_or("unknown")
+        );
+
+        self.entries
+            .insert(binary.to_path_buf(), (modified, capabilities.clone()));
+        Ok(capabilities)
+    }
+}
+
+fn probe(binary: &Path, env: &[(String, String)]) -> zed::Result<Capabilities> {
+    let help = run(binary, env, "--help")?;
+    let version = run(binary, env, "--version").ok().and_then(|output| {
+        output
+            .split_whitespace()
+            .find_map(version::parse_version)
+            .map(|version| version.to_string())
+    });
+
+    Ok(Capabilities {
+        version,
+        subcommands: subcommands(&help),
+    })
+}
+
+fn run(binary: &Path, env: &[(String, String)], flag: &str) -> zed::Result<String> {
+    let binary = binary.to_str().ok_or("Invalid binary path")?;
+    let output = zed::process::Command::new(binary)
+        .arg(flag)
+        .envs(env.iter().cloned())
+        .output()?;
+
+    if output.status != Some(0) {
+        return Err(format!(
+            "`{binary} {flag}` failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Reads the subcommand names from clap's `Commands:` section.
+fn subcommands(help: &str) -> Vec<String> {
+    help.lines()
+        .skip_while(|line| !line.starts_with("Commands:"))
+        .skip(1)
+        .take_while(|line| line.starts_with(' '))
+        .filter_map(|line| line.split_whitespace().next())
+        .map(String::from)
+        .collect()
+}
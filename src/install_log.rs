@@ -0,0 +1,71 @@
+//! An opt-in, local-only record of install attempts, kept in the extension's
+//! working directory for debugging recurring failures.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const LOG_FILE: &str = "install.log";
+const ROTATED_LOG_FILE: &str = "install.log.1";
+const MAX_LOG_SIZE: u64 = 64 * 1024;
+
+/// Appends one line describing an install attempt.
+///
+/// `outcome` is the resolved source (e.g. `downloaded v0.0.40`) or the error.
+pub fn record(outcome: Result<String, &str>, duration: Duration) {
+    rotate_if_full();
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let line = match outcome {
+        Ok(source) => format!(
+            "{timestamp} success source=\"{source}\" duration_ms={}",
+            duration.as_millis()
+        ),
+        Err(error) => format!(
+            "{timestamp} failure category={} duration_ms={} error=\"{}\"",
+            error_category(error),
+            duration.as_millis(),
+            error.replace('"', "'").replace('\n', " ")
+        ),
+    };
+
+    let file = OpenOptions::new().create(true).append(true).open(LOG_FILE);
+    if let Ok(mut file) = file {
+        writeln!(file, "{line}").ok();
+    }
+}
+
+fn rotate_if_full() {
+    let is_full = fs::metadata(LOG_FILE).is_ok_and(|metadata| metadata.len() >= MAX_LOG_SIZE);
+    if is_full {
+        fs::rename(LOG_FILE, ROTATED_LOG_FILE).ok();
+    }
+}
+
+fn error_category(error: &str) -> &'static str {
+    let error = error.to_ascii_lowercase();
+    if error.contains("disk space") {
+        return "disk";
+    }
+
+    // Checked before the asset arm, since a missing `.minisig` mentions "no asset".
+    if error.contains("sha-256")
+        || error.contains("checksum")
+        || error.contains("signature")
+        || error.contains("verify command")
+    {
+        return "verification";
+    }
+
+    if error.contains("no compatible") || error.contains("no asset") || error.contains("archive") {
+        return "asset";
+    }
+
+    if error.contains("fetch") || error.contains("download") {
+        return "network";
+    }
+
+    "other"
+}
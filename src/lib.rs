@@ -1,27 +1,115 @@
+use std::collections::BTreeMap;
+use std::fmt;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
 
+use semver::Version;
+use sha2::{Digest, Sha256};
 use zed_extension_api::{
-    self as zed, Extension, LanguageServerId, Worktree, register_extension, settings::LspSettings,
+    self as zed, Extension, LanguageServerId, Worktree, register_extension,
+    serde_json::{self, Value},
 };
 
+mod capabilities;
+mod install_log;
+mod minisign;
+mod settings;
+mod version;
+
+use capabilities::CapabilityCache;
+use settings::{BinarySettings, Compression, RumdlSettings};
+
 pub struct Rumdl {
+    /// The managed binary resolved earlier in this session. Binaries found on
+    /// PATH are looked up afresh on every start and never stored here.
+    ///
+    /// Zed drives the extension through `&mut self`, so language servers that
+    /// start at the same time are resolved one after another: the first one
+    /// fills this in and the rest reuse it instead of installing again. No
+    /// lock file is involved, so a crashed editor can't leave one behind.
     binary_cache: Option<PathBuf>,
+    /// [`BinarySettings::resolution_hash`] of the settings `binary_cache` was
+    /// resolved with.
+    binary_cache_settings: Option<u64>,
+    last_install_failure: Option<InstallFailure>,
+    capabilities: CapabilityCache,
+    /// Workspace configurations by server id and worktree id, along with the
+    /// raw settings they were computed from.
+    workspace_configurations: BTreeMap<(String, u64), (Option<Value>, Option<Value>)>,
+}
+
+/// A failed install, replayed instead of retrying until the cooldown passes.
+struct InstallFailure {
+    error: String,
+    failed_at: Instant,
+    settings: BinarySettings,
 }
 
 #[derive(Clone)]
 struct RumdlBinary {
     path: PathBuf,
-    env: Vec<(String, String)>,
+    source: BinarySource,
+}
+
+/// Where a resolved binary came from.
+#[derive(Clone, Debug)]
+enum BinarySource {
+    /// Found on PATH or in a well-known install location.
+    Path,
+    /// A previously downloaded binary.
+    Cache,
+    /// Freshly downloaded from the release with this version.
+    Downloaded(String),
+}
+
+impl fmt::Display for BinarySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Path => write!(f, "PATH"),
+            Self::Cache => write!(f, "cached"),
+            Self::Downloaded(version) => write!(f, "downloaded {version}"),
+        }
+    }
 }
 
 const NAME: &str = "rumdl";
 const NAME_PREFIX: &str = "rumdl-";
 const RUMDL_GITHUB_REPO: &str = "rvben/rumdl";
+/// The default `binary.subcommand`.
+const SERVER_SUBCOMMAND: &str = "server";
+const CLEAR_CACHE_COMMAND: &str = "rumdl-clear-cache";
+const CONFIG_FILES: &[&str] = &[".rumdl.toml", "rumdl.toml", "pyproject.toml"];
+const RETRY_ATTEMPTS: u32 = 3;
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+/// Compressed formats Zed can't unpack, which must not be mistaken for bare binaries.
+const UNSUPPORTED_ASSET_EXTENSIONS: &[&str] = &[".xz", ".bz2", ".zst", ".7z", ".rar"];
+const INSTALL_FAILURE_COOLDOWN: Duration = Duration::from_secs(30);
+const CHMOD_ATTEMPTS: u32 = 3;
+const CHMOD_RETRY_DELAY: Duration = Duration::from_millis(100);
+/// Real rumdl builds are megabytes; anything this small is a truncated download.
+const MIN_BINARY_SIZE: u64 = 64 * 1024;
+/// How many folders deep an archive may nest the binary.
+const ARCHIVE_SEARCH_DEPTH: usize = 8;
+/// Checksum and signature files are a few lines; anything bigger isn't one.
+const SMALL_FILE_MAX_SIZE: usize = 16 * 1024;
+const SMALL_FILE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Set by the clear-cache slash command, which only gets `&self`, and consumed
+/// by the next [`Rumdl::get_binary`].
+static CACHE_CLEARED: AtomicBool = AtomicBool::new(false);
 
 impl Rumdl {
     const fn new() -> Self {
-        Self { binary_cache: None }
+        Self {
+            binary_cache: None,
+            binary_cache_settings: None,
+            last_install_failure: None,
+            capabilities: CapabilityCache::new(),
+            workspace_configurations: BTreeMap::new(),
+        }
     }
 
     fn arch_name(arch: zed::Architecture) -> zed::Result<&'static str> {
@@ -36,29 +124,81 @@ impl Rumdl {
         Err(format!("Unsupported architecture: {arch:?}"))
     }
 
-    fn os_asset_info(platform: zed::Os) -> (&'static str, &'static str) {
+    /// Returns the OS component of the asset name and the asset extensions
+    /// usable on that platform, most preferred first. An empty extension
+    /// stands for a bare, uncompressed binary.
+    fn os_asset_info(platform: zed::Os) -> (&'static str, &'static [&'static str]) {
         if platform == zed::Os::Mac {
-            return ("apple-darwin", "tar.gz");
+            return ("apple-darwin", &["tar.gz", "tgz", "zip", "gz", ""]);
         }
 
         if platform == zed::Os::Linux {
-            return ("unknown-linux-gnu", "tar.gz");
+            return ("unknown-linux-gnu", &["tar.gz", "tgz", "zip", "gz", ""]);
+        }
+
+        ("pc-windows-msvc", &["zip", "tar.gz", "tgz", "gz", "exe"])
+    }
+
+    fn downloaded_file_type(asset_name: &str) -> zed::Result<zed::DownloadedFileType> {
+        if asset_name.ends_with(".zip") {
+            return Ok(zed::DownloadedFileType::Zip);
         }
 
-        ("pc-windows-msvc", "zip")
+        if asset_name.ends_with(".tar.gz") || asset_name.ends_with(".tgz") {
+            return Ok(zed::DownloadedFileType::GzipTar);
+        }
+
+        if asset_name.ends_with(".gz") {
+            return Ok(zed::DownloadedFileType::Gzip);
+        }
+
+        if UNSUPPORTED_ASSET_EXTENSIONS
+            .iter()
+            .any(|ext| asset_name.ends_with(ext))
+        {
+            return Err(format!("Unsupported archive type for asset `{asset_name}`"));
+        }
+
+        Ok(zed::DownloadedFileType::Uncompressed)
     }
 
+    /// Picks the asset for `{arch_name}-{os_str}`.
+    ///
+    /// When several assets match, release builds win over debug builds, then
+    /// earlier entries in `file_exts` win, then `{arch}-{os}` naming wins over
+    /// `{os}-{arch}`. Remaining ties are broken by asset name.
     fn find_release_asset<'a>(
         release: &'a zed::GithubRelease,
         arch_name: &str,
         os_str: &str,
-        file_ext: &str,
+        file_exts: &[&str],
     ) -> zed::Result<&'a zed::GithubReleaseAsset> {
-        let asset_name = format!("{arch_name}-{os_str}.{file_ext}");
+        // Some release tooling emits `{os}-{arch}` instead of `{arch}-{os}`.
+        let triples = [
+            format!("{arch_name}-{os_str}"),
+            format!("{os_str}-{arch_name}"),
+        ];
+
+        let rank = |asset: &zed::GithubReleaseAsset| {
+            let (ext_rank, triple_rank) = file_exts.iter().enumerate().find_map(|(i, ext)| {
+                triples
+                    .iter()
+                    .position(|triple| match *ext {
+                        "" => asset.name.ends_with(triple.as_str()),
+                        _ => asset.name.ends_with(&format!("{triple}.{ext}")),
+                    })
+                    .map(|j| (i, j))
+            })?;
+            let is_debug = asset.name.to_ascii_lowercase().contains("debug");
+            Some((is_debug, ext_rank, triple_rank))
+        };
+
         let asset = release
             .assets
             .iter()
-            .find(|a| a.name.ends_with(&asset_name));
+            .filter_map(|asset| rank(asset).map(|rank| (rank, asset)))
+            .min_by(|(a_rank, a), (b_rank, b)| a_rank.cmp(b_rank).then(a.name.cmp(&b.name)))
+            .map(|(_, asset)| asset);
 
         let Some(asset) = asset else {
             return Err(format!(
@@ -72,10 +212,10 @@ impl Rumdl {
     fn find_linux_musl_asset<'a>(
         release: &'a zed::GithubRelease,
         arch_name: &str,
-        file_ext: &str,
+        file_exts: &[&str],
         gnu_error: &str,
     ) -> zed::Result<&'a zed::GithubReleaseAsset> {
-        Self::find_release_asset(release, arch_name, "unknown-linux-musl", file_ext)
+        Self::find_release_asset(release, arch_name, "unknown-linux-musl", file_exts)
             .map_err(|musl_err| format!("{musl_err}; gnu attempt failed: {gnu_error}"))
     }
 
@@ -83,9 +223,18 @@ impl Rumdl {
         release: &'a zed::GithubRelease,
         arch_name: &str,
         platform: zed::Os,
+        os_override: Option<&str>,
+        prefer_compression: Option<Compression>,
     ) -> zed::Result<&'a zed::GithubReleaseAsset> {
-        let (os_str, file_ext) = Self::os_asset_info(platform);
-        let gnu_asset = Self::find_release_asset(release, arch_name, os_str, file_ext);
+        let (os_str, file_exts) = Self::os_asset_info(platform);
+        let file_exts = &Self::prioritize_exts(file_exts, prefer_compression);
+
+        // An explicit OS string replaces the built-in one, musl fallback included.
+        if let Some(os_override) = os_override {
+            return Self::find_release_asset(release, arch_name, os_override, file_exts);
+        }
+
+        let gnu_asset = Self::find_release_asset(release, arch_name, os_str, file_exts);
 
         if platform != zed::Os::Linux {
             return gnu_asset;
@@ -98,9 +247,44 @@ impl Rumdl {
         let gnu_error = gnu_asset
             .err()
             .unwrap_or_else(|| "unknown linux gnu asset failure".into());
-        Self::find_linux_musl_asset(release, arch_name, file_ext, &gnu_error)
+        Self::find_linux_musl_asset(release, arch_name, file_exts, &gnu_error)
+    }
+
+    /// Moves the extensions of the preferred compression to the front, keeping
+    /// the platform order otherwise, so other formats still act as fallbacks.
+    fn prioritize_exts(
+        file_exts: &[&'static str],
+        prefer_compression: Option<Compression>,
+    ) -> Vec<&'static str> {
+        let Some(prefer_compression) = prefer_compression else {
+            return file_exts.to_vec();
+        };
+
+        let (mut preferred, others): (Vec<_>, Vec<_>) = file_exts
+            .iter()
+            .partition(|ext| prefer_compression.matches(ext));
+        preferred.extend(others);
+        preferred
+    }
+
+    fn find_named_asset<'a>(
+        release: &'a zed::GithubRelease,
+        asset_name: &str,
+    ) -> zed::Result<&'a zed::GithubReleaseAsset> {
+        release
+            .assets
+            .iter()
+            .find(|a| a.name == asset_name)
+            .ok_or_else(|| {
+                format!(
+                    "Release {} has no asset named `{asset_name}`",
+                    release.version
+                )
+            })
     }
 
+    /// The canonical binary path every install ends up at, wherever the
+    /// archive nested the binary.
     fn build_versioned_binary_path(
         release_version: &str,
         platform: zed::Os,
@@ -113,7 +297,7 @@ impl Rumdl {
             return Err("Invalid release version: contains '\\\\'".into());
         }
 
-        let version_dir = format!("{NAME}-{release_version}");
+        let version_dir = format!("{NAME}-{}", version::to_dir_name(release_version));
         let mut binary_path = PathBuf::from(&version_dir).join(NAME);
 
         if platform == zed::Os::Windows {
@@ -123,61 +307,584 @@ impl Rumdl {
         Ok((version_dir, binary_path))
     }
 
+    fn is_disk_full_error(error: &str) -> bool {
+        let error = error.to_ascii_lowercase();
+        ["no space left", "disk full", "enospc", "not enough space"]
+            .iter()
+            .any(|marker| error.contains(marker))
+    }
+
     fn download_binary(
         language_server_id: &LanguageServerId,
         version_dir: &str,
         binary_path: &Path,
         asset: &zed::GithubReleaseAsset,
-        platform: zed::Os,
+        expected_sha256: Option<&str>,
+        archive_member: Option<&str>,
     ) -> zed::Result<()> {
         zed::set_language_server_installation_status(
             language_server_id,
             &zed::LanguageServerInstallationStatus::Downloading,
         );
 
-        let file_type = match platform {
-            zed::Os::Windows => zed::DownloadedFileType::Zip,
-            _ => zed::DownloadedFileType::GzipTar,
+        let file_type = Self::downloaded_file_type(&asset.name)?;
+
+        // Archives are extracted into `version_dir`; single-file assets are the
+        // binary itself.
+        let is_archive = matches!(
+            file_type,
+            zed::DownloadedFileType::GzipTar | zed::DownloadedFileType::Zip
+        );
+        let download_path = if is_archive {
+            version_dir
+        } else {
+            let binary_dir = binary_path.parent().unwrap_or(Path::new(version_dir));
+            fs::create_dir_all(binary_dir)
+                .map_err(|e| format!("Failed to create {}: {e}", binary_dir.display()))?;
+            binary_path.to_str().ok_or("Invalid binary path")?
         };
 
-        zed::download_file(&asset.download_url, version_dir, file_type)
-            .map_err(|e| format!("Failed to download Rumdl binary: {e}"))?;
+        // Neither the asset size nor the free space is visible from the
+        // extension sandbox, so running out of disk can only be reported after
+        // the fact.
+        zed::download_file(&asset.download_url, download_path, file_type).map_err(|e| {
+            if Self::is_disk_full_error(&e) {
+                return format!("Not enough free disk space to install Rumdl: {e}");
+            }
+
+            format!("Failed to download Rumdl binary: {e}")
+        })?;
+
+        if is_archive {
+            Self::normalize_archive_layout(version_dir, binary_path, archive_member)?;
+        }
+
+        Self::check_binary_file(binary_path)?;
+
+        if let Some(expected_sha256) = expected_sha256 {
+            Self::verify_sha256(binary_path, expected_sha256)?;
+        }
+
+        Self::make_executable(binary_path)
+    }
+
+    /// Catches malformed archives that put something other than a file at the
+    /// binary's path, and empty bodies some proxies answer with that Zed
+    /// happily "downloads".
+    fn check_binary_file(binary_path: &Path) -> zed::Result<()> {
+        let metadata = fs::metadata(binary_path).map_err(|e| {
+            // Following a symlink loop fails here, while the link itself is
+            // still there.
+            if fs::symlink_metadata(binary_path).is_ok_and(|m| m.file_type().is_symlink()) {
+                return format!(
+                    "Expected the Rumdl binary at {} to be a file, found a broken symlink: {e}",
+                    binary_path.display()
+                );
+            }
+
+            format!("Failed to read {}: {e}", binary_path.display())
+        })?;
+
+        if !metadata.is_file() {
+            let file_type = if metadata.is_dir() {
+                "directory"
+            } else {
+                "special file"
+            };
+            return Err(format!(
+                "Expected the Rumdl binary at {} to be a file, found a {file_type}",
+                binary_path.display()
+            ));
+        }
+
+        let size = metadata.len();
+        if size < MIN_BINARY_SIZE {
+            return Err(format!(
+                "Downloaded Rumdl binary at {} is only {size} bytes, the download was likely truncated",
+                binary_path.display()
+            ));
+        }
+
+        Ok(())
+    }
 
+    /// Network mounts and antivirus hooks occasionally make this fail spuriously.
+    fn make_executable(binary_path: &Path) -> zed::Result<()> {
         let binary_path = binary_path.to_str().ok_or("Invalid binary path")?;
-        zed::make_file_executable(binary_path)
-            .map_err(|e| format!("Failed to make binary executable: {e}"))?;
+
+        let mut attempt = 1;
+        loop {
+            match zed::make_file_executable(binary_path) {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt >= CHMOD_ATTEMPTS => {
+                    return Err(format!(
+                        "Failed to make binary executable at {binary_path}: {e}"
+                    ));
+                }
+                Err(_) => {
+                    thread::sleep(CHMOD_RETRY_DELAY);
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    /// Moves the binary to `binary_path` wherever the archive nests it, e.g.
+    /// in a folder named after the target triple
+    /// (`x86_64-pc-windows-msvc/rumdl.exe`) or several folders deep. Everything
+    /// downstream only deals with the canonical path.
+    ///
+    /// An `archive_member` is where the binary is moved from, taken literally
+    /// and never searched for, so a wrong path can't silently pick up another
+    /// executable.
+    fn normalize_archive_layout(
+        version_dir: &str,
+        binary_path: &Path,
+        archive_member: Option<&str>,
+    ) -> zed::Result<()> {
+        let nested_binary = match archive_member {
+            Some(archive_member) => {
+                let member_path = Path::new(version_dir).join(archive_member);
+                if !member_path.is_file() {
+                    return Err(format!(
+                        "Extracted archive has no `{archive_member}` (set by `binary.archive_member`) in {version_dir}"
+                    ));
+                }
+                if member_path == binary_path {
+                    return Ok(());
+                }
+                member_path
+            }
+            None if binary_path.is_file() => return Ok(()),
+            None => {
+                let file_name = binary_path.file_name().ok_or("Invalid binary path")?;
+                Self::find_nested_file(Path::new(version_dir), file_name).ok_or_else(|| {
+                    format!(
+                        "Extracted archive has no {} in {version_dir}",
+                        file_name.to_string_lossy()
+                    )
+                })?
+            }
+        };
+
+        fs::rename(&nested_binary, binary_path).map_err(|e| {
+            format!(
+                "Failed to move {} to {}: {e}",
+                nested_binary.display(),
+                binary_path.display()
+            )
+        })
+    }
+
+    /// Searches breadth-first, so the shallowest match wins; ties are broken
+    /// by path. Symlinked directories aren't followed, which rules out loops.
+    fn find_nested_file(dir: &Path, file_name: &std::ffi::OsStr) -> Option<PathBuf> {
+        let mut level = vec![dir.to_path_buf()];
+        for _ in 0..ARCHIVE_SEARCH_DEPTH {
+            let mut entries: Vec<_> = level
+                .iter()
+                .filter_map(|dir| fs::read_dir(dir).ok())
+                .flat_map(|entries| entries.flatten())
+                .collect();
+            entries.sort_by_key(|entry| entry.path());
+
+            if let Some(entry) = entries.iter().find(|entry| {
+                entry.file_name() == file_name
+                    && entry.file_type().is_ok_and(|file_type| file_type.is_file())
+            }) {
+                return Some(entry.path());
+            }
+
+            level = entries
+                .iter()
+                .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+                .map(|entry| entry.path())
+                .collect();
+            if level.is_empty() {
+                break;
+            }
+        }
+
+        None
+    }
+
+    fn verify_sha256(binary_path: &Path, expected_sha256: &str) -> zed::Result<()> {
+        let contents = fs::read(binary_path).map_err(|e| {
+            format!(
+                "Failed to read {} for verification: {e}",
+                binary_path.display()
+            )
+        })?;
+        let actual_sha256: String = Sha256::digest(&contents)
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+
+        if !actual_sha256.eq_ignore_ascii_case(expected_sha256.trim()) {
+            return Err(format!(
+                "SHA-256 mismatch for {}: expected {expected_sha256}, got {actual_sha256}",
+                binary_path.display()
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Looks up the SHA-256 digest the release publishes for `asset` in a
+    /// `<asset>.sha256` file.
+    fn release_checksum(
+        release: &zed::GithubRelease,
+        asset: &zed::GithubReleaseAsset,
+        follow_redirects: bool,
+    ) -> zed::Result<String> {
+        Self::check_kept_as_is(asset, "published checksum")?;
+
+        let checksum_name = format!("{}.sha256", asset.name);
+        let checksum_asset = Self::find_named_asset(release, &checksum_name)?;
+        let contents = Self::fetch_small_file(
+            &checksum_asset.download_url,
+            "checksum file",
+            follow_redirects,
+        )?;
+
+        contents
+            .lines()
+            .find_map(|line| {
+                let mut fields = line.split_whitespace();
+                let digest = fields.next()?;
+                let name = fields.next().map(|name| name.trim_start_matches('*'));
+                (name.is_none_or(|name| name == asset.name)).then_some(digest)
+            })
+            .filter(|digest| digest.len() == 64 && digest.chars().all(|c| c.is_ascii_hexdigit()))
+            .map(String::from)
+            .ok_or_else(|| format!("No SHA-256 digest for `{}` in {checksum_name}", asset.name))
+    }
+
+    /// Zed unpacks archives without keeping them, so only bare binaries can be
+    /// checked against a published digest or signature.
+    fn check_kept_as_is(asset: &zed::GithubReleaseAsset, what: &str) -> zed::Result<()> {
+        if !matches!(
+            Self::downloaded_file_type(&asset.name)?,
+            zed::DownloadedFileType::Uncompressed
+        ) {
+            return Err(format!(
+                "`{}` is unpacked on download, so its {what} can't be checked",
+                asset.name
+            ));
+        }
 
         Ok(())
     }
 
+    /// Fetches the release's `<asset>.minisig` minisign signature for `asset`.
+    fn release_signature(
+        release: &zed::GithubRelease,
+        asset: &zed::GithubReleaseAsset,
+        follow_redirects: bool,
+    ) -> zed::Result<String> {
+        Self::check_kept_as_is(asset, "signature")
+            .and_then(|()| {
+                let signature_name = format!("{}.minisig", asset.name);
+                let signature_asset = Self::find_named_asset(release, &signature_name)?;
+                Self::fetch_small_file(
+                    &signature_asset.download_url,
+                    "signature file",
+                    follow_redirects,
+                )
+            })
+            .map_err(|e| format!("Signature verification required: {e}"))
+    }
+
+    fn verify_signature(binary_path: &Path, public_key: &str, signature: &str) -> zed::Result<()> {
+        let contents = fs::read(binary_path).map_err(|e| {
+            format!(
+                "Failed to read {} for verification: {e}",
+                binary_path.display()
+            )
+        })?;
+
+        minisign::verify(public_key, signature, &contents).map_err(|e| {
+            format!(
+                "Signature verification failed for {}: {e}",
+                binary_path.display()
+            )
+        })
+    }
+
+    /// Streams a small release file (a checksum or signature) so an oversized
+    /// or stalling response is cut off. The host call can't be interrupted,
+    /// so the timeout is only checked between chunks.
+    fn fetch_small_file(
+        url: &str,
+        description: &str,
+        follow_redirects: bool,
+    ) -> zed::Result<String> {
+        use zed::http_client::{HttpMethod, HttpRequest, RedirectPolicy};
+
+        let started_at = Instant::now();
+        let redirect_policy = if follow_redirects {
+            RedirectPolicy::FollowAll
+        } else {
+            // Neither the status nor the headers of a stream are exposed, so
+            // a redirect can only be spotted with a separate request.
+            let response = HttpRequest::builder()
+                .method(HttpMethod::Head)
+                .url(url)
+                .build()?
+                .fetch()
+                .map_err(|e| format!("Failed to fetch {description}: {e}"))?;
+            if let Some((_, location)) = response
+                .headers
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case("location"))
+            {
+                return Err(format!(
+                    "The {description} redirects to {location}, which `binary.follow_redirects` forbids"
+                ));
+            }
+
+            RedirectPolicy::NoFollow
+        };
+
+        let stream = HttpRequest::builder()
+            .method(HttpMethod::Get)
+            .url(url)
+            .redirect_policy(redirect_policy)
+            .build()?
+            .fetch_stream()
+            .map_err(|e| format!("Failed to fetch {description}: {e}"))?;
+
+        let mut contents = Vec::new();
+        while let Some(chunk) = stream
+            .next_chunk()
+            .map_err(|e| format!("Failed to fetch {description}: {e}"))?
+        {
+            contents.extend(chunk);
+            if contents.len() > SMALL_FILE_MAX_SIZE {
+                return Err(format!(
+                    "The {description} is larger than {SMALL_FILE_MAX_SIZE} bytes"
+                ));
+            }
+
+            if started_at.elapsed() > SMALL_FILE_TIMEOUT {
+                return Err(format!(
+                    "Timed out fetching {description} after {}s",
+                    SMALL_FILE_TIMEOUT.as_secs()
+                ));
+            }
+        }
+
+        String::from_utf8(contents).map_err(|_| format!("The {description} is not valid UTF-8"))
+    }
+
+    /// Runs `binary.verify_command` with the absolute binary path appended.
+    fn run_verify_command(verify_command: &[String], binary_path: &Path) -> zed::Result<()> {
+        let Some((program, args)) = verify_command.split_first() else {
+            return Err("binary.verify_command must not be empty".into());
+        };
+
+        let binary_path = std::env::current_dir()
+            .map_err(|e| format!("Failed to resolve the extension directory: {e}"))?
+            .join(binary_path);
+        let binary_path = binary_path.to_str().ok_or("Invalid binary path")?;
+
+        let output = zed::process::Command::new(program)
+            .args(args)
+            .arg(binary_path)
+            .output()
+            .map_err(|e| format!("Failed to run verify command `{program}`: {e}"))?;
+
+        if output.status == Some(0) {
+            return Ok(());
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let captured = [stdout.trim(), stderr.trim()]
+            .into_iter()
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        Err(format!(
+            "Verify command `{program}` rejected {binary_path} (status {:?}): {captured}",
+            output.status
+        ))
+    }
+
     fn download_binary_or_cleanup(
         language_server_id: &LanguageServerId,
         version_dir: &str,
         binary_path: &Path,
         asset: &zed::GithubReleaseAsset,
-        platform: zed::Os,
+        expected_sha256: Option<&str>,
+        archive_member: Option<&str>,
     ) -> zed::Result<()> {
         let download_result = Self::download_binary(
             language_server_id,
             version_dir,
             binary_path,
             asset,
-            platform,
+            expected_sha256,
+            archive_member,
         );
         if download_result.is_ok() {
             return Ok(());
         }
 
-        // Best-effort cleanup to avoid leaving partial installs behind.
+        // Best-effort cleanup to avoid leaving partial installs behind. Zed
+        // downloads and extracts in one host call without exposing the partial
+        // bytes or range requests, and never keeps the archive, so there is
+        // nothing to resume or re-extract from anyway.
         fs::remove_dir_all(version_dir).ok();
         download_result
     }
 
-    fn cleanup_other_versions(current_version_dir: &str) {
+    /// Where `asset` can be downloaded from, in the order they're tried.
+    ///
+    /// Mirrors are expected to serve `<base>/<tag>/<asset name>`. Release
+    /// metadata always comes from GitHub.
+    fn download_sources(
+        asset: &zed::GithubReleaseAsset,
+        tag: &str,
+        settings: &BinarySettings,
+    ) -> Vec<(String, String)> {
+        let mirrors = settings
+            .mirrors
+            .iter()
+            .map(|base| (base.clone(), Self::mirror_url(base, tag, &asset.name)));
+
+        let github = ("GitHub".to_string(), asset.download_url.clone());
+        if settings.mirrors_only {
+            return mirrors.collect();
+        }
+
+        std::iter::once(github).chain(mirrors).collect()
+    }
+
+    /// Builds a mirror URL from the asset's name alone, since GitHub's
+    /// `download_url` may be shaped arbitrarily (e.g. nested per-asset paths).
+    fn mirror_url(base: &str, tag: &str, asset_name: &str) -> String {
+        format!(
+            "{}/{}/{}",
+            base.trim_end_matches('/'),
+            Self::encode_path_segment(tag),
+            Self::encode_path_segment(asset_name)
+        )
+    }
+
+    fn encode_path_segment(segment: &str) -> String {
+        segment
+            .bytes()
+            .map(|byte| match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                    char::from(byte).to_string()
+                }
+                _ => format!("%{byte:02X}"),
+            })
+            .collect()
+    }
+
+    /// Downloads `asset` from the first source that succeeds.
+    fn download_from_sources(
+        language_server_id: &LanguageServerId,
+        version_dir: &str,
+        binary_path: &Path,
+        asset: &zed::GithubReleaseAsset,
+        tag: &str,
+        expected_sha256: Option<&str>,
+        settings: &BinarySettings,
+    ) -> zed::Result<()> {
+        let mut errors = Vec::new();
+        for (source, download_url) in Self::download_sources(asset, tag, settings) {
+            let asset = zed::GithubReleaseAsset {
+                name: asset.name.clone(),
+                download_url,
+            };
+            match Self::download_binary_or_cleanup(
+                language_server_id,
+                version_dir,
+                binary_path,
+                &asset,
+                expected_sha256,
+                settings.archive_member.as_deref(),
+            ) {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    eprintln!("[rumdl] download from {source} failed: {e}");
+                    errors.push((source, e));
+                }
+            }
+        }
+
+        // A single source has nothing to summarize.
+        if let [(_, error)] = errors.as_slice() {
+            return Err(error.clone());
+        }
+
+        let errors: Vec<_> = errors
+            .iter()
+            .map(|(source, e)| format!("{source}: {e}"))
+            .collect();
+        Err(format!(
+            "Failed to download Rumdl binary from every source ({})",
+            errors.join("; ")
+        ))
+    }
+
+    fn parse_version_dir(name: &str) -> Option<Version> {
+        version::parse_version(&version::from_dir_name(name.strip_prefix(NAME_PREFIX)?))
+    }
+
+    /// Removes every `rumdl-<version>` directory, returning how many were removed.
+    fn remove_installed_versions() -> usize {
+        let Ok(entries) = fs::read_dir(".") else {
+            return 0;
+        };
+
+        entries
+            .flatten()
+            .filter(|entry| {
+                entry
+                    .file_name()
+                    .to_str()
+                    .is_some_and(|name| name.starts_with(NAME_PREFIX))
+            })
+            .filter(|entry| fs::remove_dir_all(entry.path()).is_ok())
+            .count()
+    }
+
+    /// Forgets the managed binary and any install failure once the cache has
+    /// been cleared, so the next install isn't held back by the cooldown.
+    fn forget_cleared_cache(&mut self) {
+        if CACHE_CLEARED.swap(false, Ordering::Relaxed) {
+            self.binary_cache = None;
+            self.last_install_failure = None;
+        }
+    }
+
+    /// Returns the binary of the newest `rumdl-<version>` directory already on disk.
+    fn newest_installed_binary(platform: zed::Os) -> Option<PathBuf> {
+        let entries = fs::read_dir(".").ok()?;
+
+        entries
+            .flatten()
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter_map(|name| Some((Self::parse_version_dir(&name)?, name)))
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+            .and_then(|(_, name)| {
+                let version = name.strip_prefix(NAME_PREFIX)?;
+                let (_, binary_path) = Self::build_versioned_binary_path(version, platform).ok()?;
+                binary_path.is_file().then_some(binary_path)
+            })
+    }
+
+    fn cleanup_other_versions(current_version_dir: &str, keep_versions: usize) {
         let Ok(entries) = fs::read_dir(".") else {
             return;
         };
 
+        let mut other_versions = Vec::new();
         for entry in entries.flatten() {
             let Ok(name) = entry.file_name().into_string() else {
                 continue;
@@ -191,84 +898,844 @@ impl Rumdl {
                 continue;
             }
 
-            fs::remove_dir_all(entry.path()).ok();
+            other_versions.push((Self::parse_version_dir(&name), entry.path()));
+        }
+
+        // Newest first; directories whose version can't be parsed sort last.
+        other_versions.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+        // The current version always counts towards `keep_versions`.
+        let keep_others = keep_versions.saturating_sub(1);
+        for (_, path) in other_versions.into_iter().skip(keep_others) {
+            fs::remove_dir_all(path).ok();
+        }
+    }
+
+    fn default_path(platform: zed::Os) -> &'static str {
+        match platform {
+            zed::Os::Mac => "/opt/homebrew/bin:/usr/local/bin:/usr/bin:/bin:/usr/sbin:/sbin",
+            zed::Os::Linux => "/usr/local/bin:/usr/bin:/bin:/usr/sbin:/sbin",
+            zed::Os::Windows => r"C:\Windows\System32;C:\Windows;C:\Windows\System32\Wbem",
+        }
+    }
+
+    /// Returns the worktree's shell environment, making sure it has a usable `PATH`.
+    ///
+    /// Remote and headless setups can report an empty or minimal environment,
+    /// which would leave the server unable to find anything it spawns.
+    fn shell_env(worktree: &Worktree) -> Vec<(String, String)> {
+        let mut env = worktree.shell_env();
+        let has_path = env
+            .iter()
+            .any(|(key, value)| key.eq_ignore_ascii_case("PATH") && !value.is_empty());
+        if has_path {
+            return env;
+        }
+
+        let (platform, _) = zed::current_platform();
+        let path = Self::default_path(platform);
+        eprintln!("[rumdl] shell environment has no PATH, falling back to {path}");
+
+        env.retain(|(key, _)| !key.eq_ignore_ascii_case("PATH"));
+        env.push(("PATH".into(), path.into()));
+        env
+    }
+
+    fn home_dir(env: &[(String, String)], platform: zed::Os) -> Option<PathBuf> {
+        let key = match platform {
+            zed::Os::Windows => "USERPROFILE",
+            _ => "HOME",
+        };
+
+        env.iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| PathBuf::from(value))
+    }
+
+    fn common_binary_paths(env: &[(String, String)], platform: zed::Os) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = match platform {
+            zed::Os::Mac => vec!["/opt/homebrew/bin".into(), "/usr/local/bin".into()],
+            zed::Os::Linux => vec!["/home/linuxbrew/.linuxbrew/bin".into()],
+            zed::Os::Windows => Vec::new(),
+        };
+
+        if let Some(home) = Self::home_dir(env, platform) {
+            paths.push(home.join(".cargo").join("bin"));
+
+            if platform != zed::Os::Windows {
+                paths.push(home.join(".local").join("bin"));
+            }
+        }
+
+        let binary_name = match platform {
+            zed::Os::Windows => format!("{NAME}.exe"),
+            _ => NAME.into(),
+        };
+        paths
+            .into_iter()
+            .map(|dir| dir.join(&binary_name))
+            .collect()
+    }
+
+    /// The sandbox can't see these directories, so each candidate is looked
+    /// for by trying to run it on the host.
+    fn find_common_binary(&mut self, env: &[(String, String)]) -> Option<PathBuf> {
+        let (platform, _) = zed::current_platform();
+        Self::common_binary_paths(env, platform)
+            .into_iter()
+            .filter(|path| !Self::is_managed_path(path))
+            .find(|path| self.capabilities.try_get(path, env).is_ok())
+    }
+
+    fn has_rumdl_table(pyproject: &str) -> bool {
+        pyproject
+            .lines()
+            .map(str::trim)
+            .any(|line| line == "[tool.rumdl]" || line.starts_with("[tool.rumdl."))
+    }
+
+    /// Finds the rumdl config file at the worktree root, if any, for the log
+    /// and `effective_config`.
+    ///
+    /// `pyproject.toml` only counts when it has a `[tool.rumdl]` table.
+    fn detect_config_file(worktree: &Worktree) -> Option<String> {
+        let file = CONFIG_FILES.iter().find(|file| {
+            let Ok(contents) = worktree.read_text_file(file) else {
+                return false;
+            };
+
+            **file != "pyproject.toml" || Self::has_rumdl_table(&contents)
+        })?;
+
+        let path = Path::new(&worktree.root_path()).join(file);
+        path.to_str().map(String::from)
+    }
+
+    /// Whether the system binary at `binary` can serve as the language server.
+    ///
+    /// `Ok(false)` means it can't be run at all and the managed install should
+    /// be used instead; an error means it runs but lacks `subcommand`. That's
+    /// the only capability checked: the rest is advertised by the server during
+    /// LSP initialization, so Zed only uses what an older binary supports.
+    fn check_server_support(
+        &mut self,
+        binary: &Path,
+        env: &[(String, String)],
+        subcommand: &str,
+    ) -> zed::Result<bool> {
+        // `which` happily returns shims that only work from an interactive
+        // shell (or need an interpreter that's missing). The sandbox can't
+        // inspect the file, but if the probe can't run it, Zed can't either.
+        let Some(capabilities) = self.capabilities.get(binary, env) else {
+            eprintln!(
+                "[rumdl] {} can't be run directly (a shell shim?), using the managed install instead",
+                binary.display()
+            );
+            return Ok(false);
+        };
+
+        if capabilities.supports(subcommand) {
+            return Ok(true);
+        }
+
+        Err(format!(
+            "The rumdl at {} lacks LSP support (no `{subcommand}` subcommand). \
+             Upgrade it, or remove it from PATH to use the managed install.",
+            binary.display()
+        ))
+    }
+
+    fn find_system_binary(
+        &mut self,
+        worktree: &Worktree,
+        env: &[(String, String)],
+        settings: &BinarySettings,
+    ) -> Option<PathBuf> {
+        // Admins can turn this off so only the managed binary is ever used.
+        if !settings.use_path {
+            return None;
+        }
+
+        if let Some(path) = worktree.which(NAME).map(PathBuf::from)
+            && !Self::is_managed_path(&path)
+        {
+            return Some(path);
+        }
+
+        if !settings.probe_common_paths {
+            return None;
+        }
+
+        self.find_common_binary(env)
+    }
+
+    /// Whether `path` is inside the extension directory, e.g. because a managed
+    /// `rumdl-<version>` directory was added to PATH. Such a binary would be
+    /// removed by cleanup while still in use, so it's never treated as a
+    /// system binary.
+    fn is_managed_path(path: &Path) -> bool {
+        std::env::current_dir().is_ok_and(|dir| path.starts_with(dir))
+    }
+
+    fn server_command(
+        &mut self,
+        language_server_id: &LanguageServerId,
+        worktree: &Worktree,
+    ) -> zed::Result<zed::Command> {
+        let settings = RumdlSettings::for_worktree(language_server_id, worktree)?;
+        // Zed has no way for an extension to decline a server, so an error is
+        // the clean way to keep it from starting.
+        if !settings.binary.enabled {
+            return Err("Rumdl is disabled for this worktree by `binary.enabled`".into());
+        }
+
+        // Every launch gets the shell environment, so a managed binary sees
+        // the same PATH fallback as one found on PATH.
+        let mut env = Self::shell_env(worktree);
+        let binary = self.get_binary(language_server_id, worktree, &settings.binary, &env)?;
+        eprintln!(
+            "[rumdl] using {} binary at {}",
+            binary.source,
+            binary.path.display()
+        );
+
+        if let Some(filter) = settings.binary.trace_server.log_filter()
+            && !env.iter().any(|(key, _)| key == "RUST_LOG")
+        {
+            env.push(("RUST_LOG".into(), filter.into()));
+        }
+
+        let no_color = settings.binary.no_color.unwrap_or_else(|| {
+            env.iter()
+                .any(|(key, value)| key == "NO_COLOR" && !value.is_empty())
+        });
+        if no_color && !env.iter().any(|(key, _)| key == "NO_COLOR") {
+            env.push(("NO_COLOR".into(), "1".into()));
+        }
+
+        // The argv is `[...pre_args, "server", ...config flags, ...arguments]`.
+        let mut args = settings.binary.pre_args.clone();
+        args.push(settings.binary.subcommand.clone());
+        args.extend(Self::config_args(&settings, worktree)?);
+        args.extend(settings.binary.arguments.iter().cloned());
+
+        Ok(zed::Command {
+            command: binary
+                .path
+                .to_str()
+                .ok_or("Failed to convert binary path to string")?
+                .into(),
+            args,
+            env,
+        })
+    }
+
+    /// The flags that decide which config files rumdl reads.
+    fn config_args(settings: &RumdlSettings, worktree: &Worktree) -> zed::Result<Vec<String>> {
+        let inline_config = settings.inline_config()?;
+        if inline_config.is_none() && !settings.exclude.is_empty() {
+            eprintln!(
+                "[rumdl] `exclude` only applies together with `config.inline`; add the globs to `exclude` in your rumdl config file instead"
+            );
+        }
+        // rumdl finds a detected config on its own, per file, which an explicit
+        // `--config` would turn off; it's only reported here.
+        if inline_config.is_none()
+            && !settings.ignores_config_files()
+            && let Some(config_file) = Self::detect_config_file(worktree)
+        {
+            eprintln!("[rumdl] found config file {config_file}, rumdl discovers it itself");
+        }
+
+        let Some(contents) = inline_config else {
+            return Ok(Vec::new());
+        };
+        if settings.config.no_config || settings.config.isolated {
+            eprintln!(
+                "[rumdl] `config.inline` is set, ignoring `config.no_config` and `config.isolated`"
+            );
+        }
+        let config_file = Self::write_inline_config(worktree, &contents)?;
+        Ok(vec!["--config".into(), config_file])
+    }
+
+    /// Zed asks for the workspace configuration whenever settings change, and
+    /// the sandbox can't watch files, so `config.settings_file` is simply read
+    /// afresh every time.
+    fn workspace_configuration(
+        settings: &RumdlSettings,
+        worktree: &Worktree,
+    ) -> zed::Result<Option<Value>> {
+        let settings_file = settings
+            .config
+            .settings_file
+            .as_deref()
+            .map(|path| Self::read_settings_file(worktree, path))
+            .transpose()?;
+        Ok(settings.workspace_configuration(settings_file))
+    }
+
+    fn read_settings_file(worktree: &Worktree, path: &str) -> zed::Result<Value> {
+        let path = Self::expand_path(worktree, path)?;
+        let display = path.display();
+
+        // Only the worktree and the extension directory are readable from
+        // the sandbox.
+        let relative_path = if path.is_relative() {
+            Some(path.as_path())
+        } else {
+            path.strip_prefix(worktree.root_path()).ok()
+        };
+        let contents = match relative_path.and_then(Path::to_str) {
+            Some(relative_path) => worktree.read_text_file(relative_path),
+            None => fs::read_to_string(&path).map_err(|e| e.to_string()),
+        }
+        .map_err(|e| format!("Failed to read `config.settings_file` {display}: {e}"))?;
+
+        let is_toml = path.extension().is_some_and(|ext| ext == "toml");
+        let value = if is_toml {
+            toml::from_str(&contents).map_err(|e| e.to_string())
+        } else {
+            serde_json::from_str(&contents).map_err(|e| e.to_string())
+        }
+        .map_err(|e| format!("Invalid `config.settings_file` {display}: {e}"))?;
+
+        if !matches!(value, Value::Object(_)) {
+            return Err(format!(
+                "Invalid `config.settings_file` {display}: expected an object"
+            ));
+        }
+        Ok(value)
+    }
+
+    /// Expands a leading `~` and `$VAR`/`${VAR}` references from the shell
+    /// environment.
+    fn expand_path(worktree: &Worktree, path: &str) -> zed::Result<PathBuf> {
+        let env = worktree.shell_env();
+        let lookup = |name: &str| {
+            env.iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.clone())
+                .ok_or_else(|| format!("`{name}` in `config.settings_file` is not set"))
+        };
+
+        let mut expanded = String::new();
+        let mut rest = path;
+        if let Some(after_tilde) = rest.strip_prefix('~')
+            && (after_tilde.is_empty() || after_tilde.starts_with(['/', '\\']))
+        {
+            let (platform, _) = zed::current_platform();
+            let home = Self::home_dir(&env, platform)
+                .ok_or("Can't expand `~` in `config.settings_file`: no home directory")?;
+            expanded.push_str(&home.to_string_lossy());
+            rest = after_tilde;
+        }
+
+        while let Some(start) = rest.find('$') {
+            expanded.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+            let (name, remainder) = match after.strip_prefix('{') {
+                Some(braced) => {
+                    let end = braced
+                        .find('}')
+                        .ok_or("Unterminated `${` in `config.settings_file`")?;
+                    (&braced[..end], &braced[end + 1..])
+                }
+                None => {
+                    let end = after
+                        .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                        .unwrap_or(after.len());
+                    after.split_at(end)
+                }
+            };
+
+            if name.is_empty() {
+                expanded.push('$');
+            } else {
+                expanded.push_str(&lookup(name)?);
+            }
+            rest = remainder;
+        }
+        expanded.push_str(rest);
+
+        Ok(PathBuf::from(expanded))
+    }
+
+    /// What rumdl ends up with for `worktree`: the workspace configuration
+    /// sent over LSP and where its config files come from.
+    fn effective_config(
+        settings: &RumdlSettings,
+        worktree: &Worktree,
+        workspace_configuration: Option<&Value>,
+    ) -> Value {
+        let config_files = if settings.config.inline.is_some() {
+            "config.inline"
+        } else if settings.ignores_config_files() {
+            "none"
+        } else {
+            "discovered"
+        };
+        serde_json::json!({
+            "workspace_configuration": workspace_configuration,
+            "config_files": config_files,
+            "detected_config_file": Self::detect_config_file(worktree),
+        })
+    }
+
+    /// `command` with env values redacted, so the logged launch command can be
+    /// pasted into bug reports.
+    fn debug_command(command: &zed::Command) -> zed::Command {
+        let mut command = command.clone();
+        for (_, value) in &mut command.env {
+            *value = "<redacted>".into();
+        }
+        command
+    }
+
+    /// Writes `config.inline` to a per-worktree file and returns its absolute path.
+    ///
+    /// The file is only rewritten when the contents change. The extension API
+    /// has no shutdown hook, so it's left in place for the next launch.
+    fn write_inline_config(worktree: &Worktree, contents: &str) -> zed::Result<String> {
+        let path = std::env::current_dir()
+            .map_err(|e| format!("Failed to resolve the extension directory: {e}"))?
+            .join(format!("inline-config-{}.toml", worktree.id()));
+
+        if fs::read_to_string(&path).ok().as_deref() != Some(contents) {
+            fs::write(&path, contents)
+                .map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
         }
+
+        path.to_str()
+            .map(String::from)
+            .ok_or_else(|| "Invalid inline config path".into())
     }
 
     fn get_binary(
         &mut self,
         language_server_id: &LanguageServerId,
         worktree: &Worktree,
+        settings: &BinarySettings,
+        env: &[(String, String)],
     ) -> zed::Result<RumdlBinary> {
-        if let Some(path) = worktree.which(NAME) {
-            return Ok(RumdlBinary {
-                path: PathBuf::from(path),
-                env: worktree.shell_env(),
-            });
+        self.forget_cleared_cache();
+
+        // Pinning another version and the like takes effect on the next start
+        // instead of needing an editor restart.
+        let settings_hash = settings.resolution_hash();
+        if self.binary_cache_settings != Some(settings_hash) {
+            if self.binary_cache.take().is_some() {
+                eprintln!("[rumdl] binary settings changed, resolving the binary again");
+            }
+            self.binary_cache_settings = Some(settings_hash);
+        }
+
+        let system_binary = self.find_system_binary(worktree, env, settings);
+
+        // Every fallback is recorded so one greppable line explains how the
+        // binary was found; a first-try PATH or session-cache hit stays silent.
+        let mut trail = Vec::new();
+        match system_binary {
+            Some(path)
+                if settings.trust_existing
+                    || self.check_server_support(&path, env, &settings.subcommand)? =>
+            {
+                return Ok(RumdlBinary {
+                    path,
+                    source: BinarySource::Path,
+                });
+            }
+            Some(_) => trail.push("path-unusable".to_string()),
+            None => trail.push("path-miss".to_string()),
         }
 
         if let Some(path) = &self.binary_cache
-            && path.exists()
+            && path.is_file()
         {
+            if trail.iter().any(|step| step == "path-unusable") {
+                Self::log_resolution(&trail, "cache-hit");
+            }
             return Ok(RumdlBinary {
                 path: path.clone(),
-                env: Vec::new(),
+                source: BinarySource::Cache,
             });
         }
+        trail.push("cache-miss".into());
+
+        if settings.trust_existing {
+            let (platform, _) = zed::current_platform();
+            // With a pin set, only the pinned version counts as installed.
+            let installed = if settings.tag.is_some() || settings.version.is_some() {
+                Self::installed_pinned_binary(settings, platform)
+            } else {
+                Self::newest_installed_binary(platform)
+            };
+            // A signature can only be checked against the release, so a
+            // binary that has to be verified goes through the install.
+            if let Some(path) = installed
+                .filter(|path| Self::is_trusted(path, settings) && !settings.verify_signature)
+            {
+                Self::log_resolution(&trail, "installed-hit");
+                self.binary_cache = Some(path.clone());
+                return Ok(RumdlBinary {
+                    path,
+                    source: BinarySource::Cache,
+                });
+            }
+            trail.push("installed-miss".into());
+        }
+
+        // Restarting the server right after a failed install would otherwise
+        // hammer GitHub and pile up identical error toasts.
+        if let Some(failure) = &self.last_install_failure
+            && failure.failed_at.elapsed() < INSTALL_FAILURE_COOLDOWN
+            && failure.settings == *settings
+        {
+            Self::log_resolution(&trail, "install-cooldown");
+            return Err(failure.error.clone());
+        }
+
+        let started_at = Instant::now();
+        let result = self.install_binary(language_server_id, settings);
+        let outcome = match &result {
+            Ok(RumdlBinary {
+                source: BinarySource::Downloaded(version),
+                ..
+            }) => format!("downloaded {version}"),
+            Ok(_) => "installed-hit".into(),
+            Err(_) => "install-failed".into(),
+        };
+        Self::log_resolution(&trail, &outcome);
+
+        if settings.install_log {
+            let outcome = result
+                .as_ref()
+                .map(|binary| binary.source.to_string())
+                .map_err(String::as_str);
+            install_log::record(outcome, started_at.elapsed());
+        }
+
+        self.last_install_failure = result.as_ref().err().map(|error| InstallFailure {
+            error: error.clone(),
+            failed_at: Instant::now(),
+            settings: settings.clone(),
+        });
+        result
+    }
+
+    fn log_resolution(trail: &[String], outcome: &str) {
+        eprintln!("[rumdl] resolution: {} -> {outcome}", trail.join(" -> "));
+    }
+
+    /// Missing repos/tags and rate limiting won't fix themselves by retrying.
+    fn is_permanent_error(error: &str) -> bool {
+        let error = error.to_ascii_lowercase();
+        ["404", "not found", "403", "rate limit"]
+            .iter()
+            .any(|marker| error.contains(marker))
+    }
+
+    /// Runs `operation`, retrying transient failures with exponential backoff.
+    fn with_retry<T>(mut operation: impl FnMut() -> zed::Result<T>) -> zed::Result<T> {
+        let mut delay = RETRY_BASE_DELAY;
+        let mut attempt = 1;
+        loop {
+            match operation() {
+                Ok(value) => return Ok(value),
+                Err(e) if attempt >= RETRY_ATTEMPTS || Self::is_permanent_error(&e) => {
+                    return Err(e);
+                }
+                Err(_) => {
+                    thread::sleep(delay);
+                    delay *= 2;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    /// Whether an installed binary satisfies `binary.expected_sha256`, if set.
+    fn is_trusted(binary_path: &Path, settings: &BinarySettings) -> bool {
+        let Some(expected_sha256) = &settings.expected_sha256 else {
+            return true;
+        };
+
+        Self::verify_sha256(binary_path, expected_sha256)
+            .inspect_err(|e| eprintln!("[rumdl] {e}"))
+            .is_ok()
+    }
+
+    fn installed_binary_for_version(version: &str, platform: zed::Os) -> Option<PathBuf> {
+        let (_, tags) = Self::candidate_tags(version)?;
+
+        tags.iter()
+            .filter_map(|tag| Self::build_versioned_binary_path(tag, platform).ok())
+            .map(|(_, binary_path)| binary_path)
+            .find(|binary_path| binary_path.is_file())
+    }
+
+    /// Returns the parsed version and the tags it may be released under, which
+    /// include the version as written in case the tag is abbreviated (`v0.1`).
+    fn candidate_tags(version: &str) -> Option<(Version, Vec<String>)> {
+        let wanted = version::parse_version(version)?;
+        let as_written = version.strip_prefix('v').unwrap_or(version);
+
+        let mut tags = vec![format!("v{wanted}"), wanted.to_string()];
+        for tag in [format!("v{as_written}"), as_written.to_string()] {
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        Some((wanted, tags))
+    }
+
+    /// Fetches the release for a pinned version.
+    ///
+    /// The release is only accepted when its version is semver-equal to the pin,
+    /// so a tag like `0.0.4` is never mistaken for `0.0.40`.
+    fn pinned_release(version: &str) -> zed::Result<zed::GithubRelease> {
+        let (wanted, tags) = Self::candidate_tags(version)
+            .ok_or_else(|| format!("Invalid rumdl version `{version}`"))?;
+
+        let mut errors = Vec::new();
+        for tag in tags {
+            match Self::with_retry(|| zed::github_release_by_tag_name(RUMDL_GITHUB_REPO, &tag)) {
+                Ok(release) if Self::is_release_of(&release, &wanted) => return Ok(release),
+                Ok(release) => errors.push(format!("{tag} resolved to {}", release.version)),
+                Err(e) => errors.push(format!("{tag}: {e}")),
+            }
+        }
+
+        Err(format!(
+            "Failed to fetch rumdl release {wanted}: {}",
+            errors.join("; ")
+        ))
+    }
+
+    fn is_release_of(release: &zed::GithubRelease, version: &Version) -> bool {
+        version::parse_version(&release.version).as_ref() == Some(version)
+    }
+
+    /// Fetches the release for `tag`, which doesn't need to be semver.
+    fn tagged_release(tag: &str) -> zed::Result<zed::GithubRelease> {
+        let release = Self::with_retry(|| zed::github_release_by_tag_name(RUMDL_GITHUB_REPO, tag))
+            .map_err(|e| format!("Failed to fetch rumdl release for tag {tag}: {e}"))?;
+
+        if release.assets.is_empty() {
+            return Err(format!("Tag {tag} has no downloadable assets"));
+        }
+
+        Ok(release)
+    }
+
+    /// `binary.tag` wins over `binary.version`, which wins over the latest release.
+    fn fetch_release(settings: &BinarySettings) -> zed::Result<zed::GithubRelease> {
+        if let Some(tag) = &settings.tag {
+            return Self::tagged_release(tag);
+        }
+
+        if let Some(version) = &settings.version {
+            return Self::pinned_release(version);
+        }
 
-        self.install_binary(language_server_id)
+        Self::with_retry(|| {
+            zed::latest_github_release(
+                RUMDL_GITHUB_REPO,
+                zed::GithubReleaseOptions {
+                    require_assets: true,
+                    pre_release: false,
+                },
+            )
+        })
+        .map_err(|e| format!("Failed to fetch latest release: {e}"))
+    }
+
+    fn installed_pinned_binary(settings: &BinarySettings, platform: zed::Os) -> Option<PathBuf> {
+        if let Some(tag) = &settings.tag {
+            let (_, binary_path) = Self::build_versioned_binary_path(tag, platform).ok()?;
+            return binary_path.is_file().then_some(binary_path);
+        }
+
+        Self::installed_binary_for_version(settings.version.as_deref()?, platform)
     }
 
+    /// Installs the binary, tagging errors with the platform so pasted
+    /// messages are self-describing.
     fn install_binary(
         &mut self,
         language_server_id: &LanguageServerId,
+        settings: &BinarySettings,
+    ) -> zed::Result<RumdlBinary> {
+        let (platform, arch) = zed::current_platform();
+        self.install_binary_for(language_server_id, settings, platform, arch)
+            .map_err(|e| format!("{e} (platform: {platform:?}, arch: {arch:?})"))
+    }
+
+    /// Fails before downloading when the download couldn't be stored anyway,
+    /// e.g. on locked-down machines with a read-only extension directory. An
+    /// already installed binary is still used there.
+    fn check_cache_writable() -> zed::Result<()> {
+        const PROBE_FILE: &str = ".write-probe";
+
+        fs::write(PROBE_FILE, []).map_err(|e| {
+            let dir = std::env::current_dir()
+                .map_or_else(|_| ".".into(), |dir| dir.display().to_string());
+            format!(
+                "Can't install Rumdl, the extension directory {dir} isn't writable ({e}). \
+                 Put a rumdl on PATH instead."
+            )
+        })?;
+        fs::remove_file(PROBE_FILE).ok();
+        Ok(())
+    }
+
+    fn install_binary_for(
+        &mut self,
+        language_server_id: &LanguageServerId,
+        settings: &BinarySettings,
+        platform: zed::Os,
+        arch: zed::Architecture,
     ) -> zed::Result<RumdlBinary> {
+        // An installed pinned version needs no network round-trip, so don't
+        // flash the update status for it.
+        if let Some(path) = Self::installed_pinned_binary(settings, platform)
+            .filter(|path| Self::is_trusted(path, settings) && !settings.verify_signature)
+        {
+            self.binary_cache = Some(path.clone());
+            return Ok(RumdlBinary {
+                path,
+                source: BinarySource::Cache,
+            });
+        }
+
         zed::set_language_server_installation_status(
             language_server_id,
             &zed::LanguageServerInstallationStatus::CheckingForUpdate,
         );
 
-        let release = zed::latest_github_release(
-            RUMDL_GITHUB_REPO,
-            zed::GithubReleaseOptions {
-                require_assets: true,
-                pre_release: false,
-            },
-        )
-        .map_err(|e| format!("Failed to fetch latest release: {e}"))?;
+        let release = Self::fetch_release(settings)?;
 
-        let (platform, arch) = zed::current_platform();
-        let arch_name = Self::arch_name(arch)?;
-        let asset = Self::find_release_asset_for_platform(&release, arch_name, platform)?;
+        let asset = match &settings.asset_name {
+            Some(asset_name) => Self::find_named_asset(&release, asset_name)?,
+            None => {
+                let find_asset = |arch_name| {
+                    Self::find_release_asset_for_platform(
+                        &release,
+                        arch_name,
+                        platform,
+                        settings.os_string.as_deref(),
+                        settings.prefer_compression,
+                    )
+                };
+
+                match find_asset(Self::arch_name(arch)?) {
+                    Err(e)
+                        if settings.allow_x86_emulation
+                            && platform == zed::Os::Linux
+                            && arch == zed::Architecture::Aarch64 =>
+                    {
+                        let asset = find_asset("x86_64").map_err(|x86_error| {
+                            format!("{e}; x86_64 emulation fallback failed: {x86_error}")
+                        })?;
+                        eprintln!(
+                            "[rumdl] WARNING: {} has no aarch64 Linux build, falling back to the x86_64 `{}`. \
+                             It only runs under an x86 emulation layer such as box64 or qemu-user.",
+                            release.version, asset.name
+                        );
+                        asset
+                    }
+                    result => result?,
+                }
+            }
+        };
         let (version_dir, binary_path) =
             Self::build_versioned_binary_path(&release.version, platform)?;
 
-        if binary_path.exists() {
+        let signature = settings
+            .signature_public_key
+            .as_deref()
+            .filter(|_| settings.verify_signature)
+            .map(|public_key| {
+                Self::release_signature(&release, asset, settings.follow_redirects)
+                    .map(|signature| (public_key, signature))
+            })
+            .transpose()?;
+        let verify_signature = |binary_path: &Path| {
+            signature
+                .as_ref()
+                .map_or(Ok(()), |(public_key, signature)| {
+                    Self::verify_signature(binary_path, public_key, signature)
+                })
+        };
+
+        if binary_path.exists()
+            && (!binary_path.is_file()
+                || !Self::is_trusted(&binary_path, settings)
+                || verify_signature(&binary_path).is_err())
+        {
+            // Never reuse a binary that doesn't match the vetted digest, or
+            // something that isn't a binary at all.
+            fs::remove_dir_all(&version_dir).ok();
+        }
+
+        if binary_path.is_file() {
             self.binary_cache = Some(binary_path.clone());
             return Ok(RumdlBinary {
                 path: binary_path,
-                env: Vec::new(),
+                source: BinarySource::Cache,
             });
         }
 
-        Self::download_binary_or_cleanup(
+        Self::check_cache_writable()?;
+
+        let expected_sha256 = match &settings.expected_sha256 {
+            Some(expected_sha256) => Some(expected_sha256.clone()),
+            None => match Self::release_checksum(&release, asset, settings.follow_redirects) {
+                Ok(expected_sha256) => Some(expected_sha256),
+                Err(e) if settings.require_checksum => {
+                    return Err(format!("Checksum verification required: {e}"));
+                }
+                Err(e) => {
+                    eprintln!("[rumdl] skipping checksum verification: {e}");
+                    None
+                }
+            },
+        };
+
+        Self::download_from_sources(
             language_server_id,
             &version_dir,
             &binary_path,
             asset,
-            platform,
+            &release.version,
+            expected_sha256.as_deref(),
+            settings,
         )?;
-        Self::cleanup_other_versions(&version_dir);
+
+        verify_signature(&binary_path).inspect_err(|_| {
+            fs::remove_dir_all(&version_dir).ok();
+        })?;
+
+        if let Some(verify_command) = &settings.verify_command {
+            Self::run_verify_command(verify_command, &binary_path).inspect_err(|_| {
+                fs::remove_dir_all(&version_dir).ok();
+            })?;
+        }
+
+        Self::cleanup_other_versions(&version_dir, settings.keep_versions);
 
         self.binary_cache = Some(binary_path.clone());
         Ok(RumdlBinary {
             path: binary_path,
-            env: Vec::new(),
+            source: BinarySource::Downloaded(release.version),
         })
     }
 }
 
 impl Extension for Rumdl {
+    // Settings can only be read for a worktree, so nothing settings-driven
+    // (such as pre-installing the binary) can happen before the first
+    // language server is requested.
     fn new() -> Self {
         Self::new()
     }
@@ -278,28 +1745,366 @@ impl Extension for Rumdl {
         language_server_id: &LanguageServerId,
         worktree: &Worktree,
     ) -> zed::Result<zed::Command> {
-        let binary = self.get_binary(language_server_id, worktree)?;
-        Ok(zed::Command {
-            command: binary
-                .path
-                .to_str()
-                .ok_or("Failed to convert binary path to string")?
-                .into(),
-            args: vec!["server".into()],
-            env: binary.env,
+        let command = self.server_command(language_server_id, worktree)?;
+        eprintln!("[rumdl] launching {:?}", Self::debug_command(&command));
+        Ok(command)
+    }
+
+    fn run_slash_command(
+        &self,
+        command: zed::SlashCommand,
+        _args: Vec<String>,
+        _worktree: Option<&Worktree>,
+    ) -> zed::Result<zed::SlashCommandOutput> {
+        if command.name != CLEAR_CACHE_COMMAND {
+            return Err(format!("Unknown slash command: {}", command.name));
+        }
+
+        // The session state can't be reset through `&self`, so it's left to
+        // the next language server start.
+        let removed = Self::remove_installed_versions();
+        CACHE_CLEARED.store(true, Ordering::Relaxed);
+        let text = format!(
+            "Removed {removed} downloaded rumdl version(s). Restart the language server to reinstall."
+        );
+        Ok(zed::SlashCommandOutput {
+            sections: vec![zed::SlashCommandOutputSection {
+                range: (0..text.len()).into(),
+                label: "rumdl cache cleared".into(),
+            }],
+            text,
         })
     }
 
+    // Zed calls this again whenever the settings change and forwards the result
+    // to the running server as `workspace/didChangeConfiguration`. The raw
+    // settings are always looked up to tell whether they changed; parsing and
+    // merging are only redone when they did.
     fn language_server_workspace_configuration(
         &mut self,
         server_id: &LanguageServerId,
         worktree: &Worktree,
     ) -> zed::Result<Option<zed::serde_json::Value>> {
-        let settings = LspSettings::for_worktree(server_id.as_ref(), worktree)
-            .ok()
-            .and_then(|lsp_settings| lsp_settings.settings);
-        Ok(settings)
+        let raw_settings = RumdlSettings::raw_for_worktree(server_id, worktree);
+        let key = (server_id.as_ref().to_string(), worktree.id());
+        if let Some((cached_settings, configuration)) = self.workspace_configurations.get(&key)
+            && *cached_settings == raw_settings
+        {
+            return Ok(configuration.clone());
+        }
+
+        let settings = RumdlSettings::from_raw(raw_settings.clone())?;
+        settings.warn_ignored_settings();
+        let configuration = Self::workspace_configuration(&settings, worktree)?;
+        eprintln!(
+            "[rumdl] effective configuration: {}",
+            Self::effective_config(&settings, worktree, configuration.as_ref())
+        );
+
+        // A settings file can change without the settings changing.
+        if settings.config.settings_file.is_none() {
+            self.workspace_configurations
+                .insert(key, (raw_settings, configuration.clone()));
+        }
+        Ok(configuration)
     }
 }
 
 register_extension!(Rumdl);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn release(asset_names: &[&str]) -> zed::GithubRelease {
+        zed::GithubRelease {
+            version: "v0.1.0".into(),
+            assets: asset_names
+                .iter()
+                .map(|name| zed::GithubReleaseAsset {
+                    name: (*name).into(),
+                    download_url: format!("https://example.com/{name}"),
+                })
+                .collect(),
+        }
+    }
+
+    /// A scratch directory removed again when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("zed-rumdl-{name}-{}", std::process::id()));
+            fs::remove_dir_all(&dir).ok();
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+
+        fn create_file(&self, path: &str) -> PathBuf {
+            let path = self.0.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, "binary").unwrap();
+            path
+        }
+
+        fn version_dir(&self) -> &str {
+            self.0.to_str().unwrap()
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    fn linux_asset(release: &zed::GithubRelease) -> zed::Result<&str> {
+        let (os_str, file_exts) = Rumdl::os_asset_info(zed::Os::Linux);
+        Rumdl::find_release_asset(release, "x86_64", os_str, file_exts)
+            .map(|asset| asset.name.as_str())
+    }
+
+    #[test]
+    fn finds_arch_os_asset() {
+        let release = release(&["rumdl-v0.1.0-x86_64-unknown-linux-gnu.tar.gz"]);
+        assert_eq!(
+            linux_asset(&release),
+            Ok("rumdl-v0.1.0-x86_64-unknown-linux-gnu.tar.gz")
+        );
+    }
+
+    #[test]
+    fn finds_os_arch_asset() {
+        let release = release(&["rumdl-v0.1.0-unknown-linux-gnu-x86_64.tar.gz"]);
+        assert_eq!(
+            linux_asset(&release),
+            Ok("rumdl-v0.1.0-unknown-linux-gnu-x86_64.tar.gz")
+        );
+    }
+
+    #[test]
+    fn prefers_arch_os_asset() {
+        let release = release(&[
+            "rumdl-v0.1.0-unknown-linux-gnu-x86_64.tar.gz",
+            "rumdl-v0.1.0-x86_64-unknown-linux-gnu.tar.gz",
+        ]);
+        assert_eq!(
+            linux_asset(&release),
+            Ok("rumdl-v0.1.0-x86_64-unknown-linux-gnu.tar.gz")
+        );
+    }
+
+    #[test]
+    fn prefers_release_over_debug_builds() {
+        let release = release(&[
+            "rumdl-v0.1.0-debug-x86_64-unknown-linux-gnu.tar.gz",
+            "rumdl-v0.1.0-x86_64-unknown-linux-gnu.zip",
+        ]);
+        assert_eq!(
+            linux_asset(&release),
+            Ok("rumdl-v0.1.0-x86_64-unknown-linux-gnu.zip")
+        );
+    }
+
+    #[test]
+    fn prefers_earlier_extensions() {
+        let release = release(&[
+            "rumdl-v0.1.0-x86_64-unknown-linux-gnu.zip",
+            "rumdl-v0.1.0-x86_64-unknown-linux-gnu.tar.gz",
+        ]);
+        assert_eq!(
+            linux_asset(&release),
+            Ok("rumdl-v0.1.0-x86_64-unknown-linux-gnu.tar.gz")
+        );
+    }
+
+    #[test]
+    fn breaks_ties_by_name() {
+        let release = release(&[
+            "rumdl-v0.1.0-x86_64-unknown-linux-gnu.tar.gz",
+            "rumdl-full-v0.1.0-x86_64-unknown-linux-gnu.tar.gz",
+        ]);
+        assert_eq!(
+            linux_asset(&release),
+            Ok("rumdl-full-v0.1.0-x86_64-unknown-linux-gnu.tar.gz")
+        );
+    }
+
+    #[test]
+    fn candidate_tags_stay_on_the_pinned_version() {
+        let (wanted, tags) = Rumdl::candidate_tags("0.0.4").unwrap();
+        assert_eq!(wanted, Version::new(0, 0, 4));
+        assert_eq!(tags, ["v0.0.4", "0.0.4"]);
+
+        let (_, tags) = Rumdl::candidate_tags("v0.1").unwrap();
+        assert_eq!(tags, ["v0.1.0", "0.1.0", "v0.1", "0.1"]);
+    }
+
+    #[test]
+    fn pinned_version_rejects_adjacent_releases() {
+        let wanted = Version::new(0, 0, 4);
+        let mut release = release(&[]);
+
+        release.version = "v0.0.40".into();
+        assert!(!Rumdl::is_release_of(&release, &wanted));
+
+        release.version = "0.0.4".into();
+        assert!(Rumdl::is_release_of(&release, &wanted));
+    }
+
+    #[test]
+    fn keeps_flat_archive_layout() {
+        let dir = TempDir::new("flat");
+        let binary_path = dir.create_file("rumdl");
+
+        Rumdl::normalize_archive_layout(dir.version_dir(), &binary_path, None).unwrap();
+        assert!(binary_path.is_file());
+    }
+
+    #[test]
+    fn moves_binary_out_of_triple_folder() {
+        let dir = TempDir::new("triple");
+        let nested = dir.create_file("x86_64-pc-windows-msvc/rumdl.exe");
+        let binary_path = dir.0.join("rumdl.exe");
+
+        Rumdl::normalize_archive_layout(dir.version_dir(), &binary_path, None).unwrap();
+        assert!(binary_path.is_file());
+        assert!(!nested.exists());
+    }
+
+    #[test]
+    fn moves_deeply_nested_binary() {
+        let dir = TempDir::new("deep");
+        dir.create_file("rumdl-v0.1.0/dist/release/bin/rumdl");
+        let binary_path = dir.0.join("rumdl");
+
+        Rumdl::normalize_archive_layout(dir.version_dir(), &binary_path, None).unwrap();
+        assert!(binary_path.is_file());
+    }
+
+    #[test]
+    fn moves_archive_member_to_canonical_path() {
+        let dir = TempDir::new("member");
+        let member = dir.create_file("dist/tools/rumdl-lsp");
+        dir.create_file("rumdl");
+        let binary_path = dir.0.join("rumdl");
+
+        Rumdl::normalize_archive_layout(
+            dir.version_dir(),
+            &binary_path,
+            Some("dist/tools/rumdl-lsp"),
+        )
+        .unwrap();
+        assert!(binary_path.is_file());
+        assert!(!member.exists());
+    }
+
+    #[test]
+    fn never_searches_for_archive_member() {
+        let dir = TempDir::new("missing-member");
+        dir.create_file("bin/rumdl");
+        let binary_path = dir.0.join("rumdl");
+
+        let error =
+            Rumdl::normalize_archive_layout(dir.version_dir(), &binary_path, Some("dist/rumdl"))
+                .unwrap_err();
+        assert!(error.contains("`dist/rumdl`"), "{error}");
+        assert!(!binary_path.exists());
+    }
+
+    #[test]
+    fn finds_shallowest_nested_file() {
+        let dir = TempDir::new("shallowest");
+        dir.create_file("a/b/rumdl");
+        let shallow = dir.create_file("b/rumdl");
+        dir.create_file("c/rumdl/readme");
+
+        let found = Rumdl::find_nested_file(&dir.0, "rumdl".as_ref()).unwrap();
+        assert_eq!(found, shallow);
+    }
+
+    #[test]
+    fn reports_missing_binary() {
+        let dir = TempDir::new("missing");
+        dir.create_file(&format!("{}rumdl", "d/".repeat(ARCHIVE_SEARCH_DEPTH)));
+        let binary_path = dir.0.join("rumdl");
+
+        let error =
+            Rumdl::normalize_archive_layout(dir.version_dir(), &binary_path, None).unwrap_err();
+        assert!(error.contains("has no rumdl"), "{error}");
+    }
+
+    #[test]
+    fn builds_mirror_urls() {
+        let asset = "rumdl-v0.1.0-x86_64-unknown-linux-gnu.tar.gz";
+        let expected = format!("https://mirror.example.com/rumdl/v0.1.0/{asset}");
+        for base in [
+            "https://mirror.example.com/rumdl",
+            "https://mirror.example.com/rumdl/",
+            "https://mirror.example.com/rumdl//",
+        ] {
+            assert_eq!(Rumdl::mirror_url(base, "v0.1.0", asset), expected);
+        }
+
+        assert_eq!(
+            Rumdl::mirror_url("http://localhost:8080", "v0.1.0", "rumdl.zip"),
+            "http://localhost:8080/v0.1.0/rumdl.zip"
+        );
+    }
+
+    #[test]
+    fn percent_encodes_mirror_url_segments() {
+        assert_eq!(
+            Rumdl::mirror_url(
+                "https://mirror.example.com",
+                "v0.1.0+build 3",
+                "rumdl/ä.zip"
+            ),
+            "https://mirror.example.com/v0.1.0%2Bbuild%203/rumdl%2F%C3%A4.zip"
+        );
+        assert_eq!(Rumdl::encode_path_segment("a-b_c.d~e"), "a-b_c.d~e");
+        assert_eq!(Rumdl::encode_path_segment("?#%"), "%3F%23%25");
+    }
+
+    #[test]
+    fn tells_managed_binaries_from_system_ones() {
+        let extension_dir = std::env::current_dir().unwrap();
+        let downloaded = extension_dir.join("rumdl-0.1.0").join("rumdl");
+        assert!(Rumdl::is_managed_path(&downloaded));
+
+        let on_path = Path::new("/usr/local/bin/rumdl");
+        assert!(!Rumdl::is_managed_path(on_path));
+
+        // A sibling directory only shares a string prefix, not a path prefix.
+        let mut sibling = extension_dir.into_os_string();
+        sibling.push("-other");
+        let sibling = PathBuf::from(sibling).join("rumdl");
+        assert!(!Rumdl::is_managed_path(&sibling));
+    }
+
+    #[test]
+    fn debug_command_redacts_env_values() {
+        let command = zed::Command {
+            command: "/usr/local/bin/rumdl".into(),
+            args: vec!["server".into()],
+            env: vec![("GITHUB_TOKEN".into(), "secret".into())],
+        };
+
+        let debug_command = Rumdl::debug_command(&command);
+        assert_eq!(debug_command.command, command.command);
+        assert_eq!(debug_command.args, command.args);
+        assert_eq!(
+            debug_command.env,
+            [("GITHUB_TOKEN".to_string(), "<redacted>".to_string())]
+        );
+    }
+
+    #[test]
+    fn rejects_other_platforms() {
+        let release = release(&[
+            "rumdl-v0.1.0-aarch64-unknown-linux-gnu.tar.gz",
+            "rumdl-v0.1.0-x86_64-apple-darwin.tar.gz",
+        ]);
+        assert!(linux_asset(&release).is_err());
+    }
+}
@@ -0,0 +1,151 @@
+//! Verification of [minisign](https://jedisct1.github.io/minisign/) detached
+//! signatures, both the legacy (`Ed`) and the pre-hashed (`ED`) flavor.
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use blake2::{Blake2b512, Digest};
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+
+const KEY_ID_LEN: usize = 8;
+
+/// Checks `data` against a `.minisig` file's contents.
+///
+/// `public_key` is the base64 key line of a minisign public key file, with or
+/// without its `untrusted comment:` line.
+pub fn verify(public_key: &str, signature_file: &str, data: &[u8]) -> Result<(), String> {
+    let (key_id, key) = parse_public_key(public_key)?;
+
+    let mut lines = signature_file
+        .lines()
+        .filter(|line| !line.starts_with("untrusted comment:"));
+    let mut next_line = |what: &str| {
+        lines
+            .next()
+            .map(str::trim)
+            .ok_or_else(|| format!("Signature file has no {what}"))
+    };
+    let signature = decode(next_line("signature")?, "signature")?;
+    let trusted_comment = next_line("trusted comment")?;
+    let global_signature = decode(next_line("global signature")?, "global signature")?;
+
+    if signature.len() != 2 + KEY_ID_LEN + Signature::BYTE_SIZE {
+        return Err("Malformed signature".into());
+    }
+    let (algorithm, rest) = signature.split_at(2);
+    let (signature_key_id, signature_bytes) = rest.split_at(KEY_ID_LEN);
+    if signature_key_id != key_id {
+        return Err("Signature was made with a different key".into());
+    }
+
+    let signature_bytes = to_signature(signature_bytes)?;
+    let verified = match algorithm {
+        b"ED" => key.verify(&Blake2b512::digest(data), &signature_bytes),
+        b"Ed" => key.verify(data, &signature_bytes),
+        _ => return Err("Unsupported signature algorithm".into()),
+    };
+    verified.map_err(|_| "Signature doesn't match".to_string())?;
+
+    // The trusted comment is signed together with the signature, so it can't
+    // be swapped for another release's.
+    let trusted_comment = trusted_comment
+        .strip_prefix("trusted comment:")
+        .ok_or("Signature file has no trusted comment")?
+        .trim_start();
+    let mut signed_comment = signature_bytes.to_bytes().to_vec();
+    signed_comment.extend_from_slice(trusted_comment.as_bytes());
+    key.verify(&signed_comment, &to_signature(&global_signature)?)
+        .map_err(|_| "Trusted comment signature doesn't match".to_string())
+}
+
+fn parse_public_key(public_key: &str) -> Result<([u8; KEY_ID_LEN], VerifyingKey), String> {
+    let line = public_key
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with("untrusted comment:"))
+        .ok_or("Public key is empty")?;
+    let bytes = decode(line, "public key")?;
+
+    let [b'E', b'd', rest @ ..] = bytes.as_slice() else {
+        return Err("Not a minisign Ed25519 public key".into());
+    };
+    let (key_id, key) = rest
+        .split_first_chunk::<KEY_ID_LEN>()
+        .ok_or("Malformed public key")?;
+    let key: &[u8; 32] = key.try_into().map_err(|_| "Malformed public key")?;
+    let key = VerifyingKey::from_bytes(key).map_err(|e| format!("Invalid public key: {e}"))?;
+
+    Ok((*key_id, key))
+}
+
+fn decode(value: &str, what: &str) -> Result<Vec<u8>, String> {
+    STANDARD
+        .decode(value)
+        .map_err(|e| format!("Malformed {what}: {e}"))
+}
+
+fn to_signature(bytes: &[u8]) -> Result<Signature, String> {
+    Signature::from_slice(bytes).map_err(|_| "Malformed signature".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Made with `rsign generate -W` and `rsign sign -W -t "timestamp:1760400000 file:data"`
+    // (rsign2 0.6.7, the Rust port of the minisign CLI).
+    const PUBLIC_KEY: &str = "untrusted comment: minisign public key: 5ED99986FB885D85
+RWSFXYj7hpnZXgVKzaDpBaNrX9gGfIx4jU6LITQ7mc4K657QomBZcGFG
+";
+    const OTHER_PUBLIC_KEY: &str = "RWStkKtvBGbHhEdEUQ0NFd7rQxi9m6d25ToL/MNSt/+U4s1+v3bYD/qe";
+    const DATA: &[u8] = b"rumdl test binary\n";
+    const SIGNATURE: &str = "untrusted comment: signature from rsign secret key
+RUSFXYj7hpnZXhnvBgukeLIaQWR8XCtVJXwY/hNttvLC+KEe7DhRJLjpZqA16gs0+S9OaJlReTPnbD+IzfynZIeYlTBe3y2PPAM=
+trusted comment: timestamp:1760400000 file:data
+jETSx+SGsD08DbN9C5qLsw23W/Wc4DY0d6YLJSKF6exPwVS355hkKduBRJfxNBk6gqxXAO3yR0QmacXABbTTAQ==
+";
+
+    #[test]
+    fn verifies_cli_signature() {
+        assert_eq!(verify(PUBLIC_KEY, SIGNATURE, DATA), Ok(()));
+
+        let key_line = PUBLIC_KEY.lines().nth(1).unwrap();
+        assert_eq!(verify(key_line, SIGNATURE, DATA), Ok(()));
+    }
+
+    #[test]
+    fn rejects_tampered_data() {
+        assert_eq!(
+            verify(PUBLIC_KEY, SIGNATURE, b"rumdl test binary!\n"),
+            Err("Signature doesn't match".into())
+        );
+    }
+
+    #[test]
+    fn rejects_tampered_trusted_comment() {
+        let signature = SIGNATURE.replace("file:data", "file:other");
+        assert_eq!(
+            verify(PUBLIC_KEY, &signature, DATA),
+            Err("Trusted comment signature doesn't match".into())
+        );
+    }
+
+    #[test]
+    fn rejects_other_keys() {
+        assert_eq!(
+            verify(OTHER_PUBLIC_KEY, SIGNATURE, DATA),
+            Err("Signature was made with a different key".into())
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        assert!(verify("", SIGNATURE, DATA).is_err());
+        assert!(verify(PUBLIC_KEY, "untrusted comment: empty\n", DATA).is_err());
+
+        let truncated: String = SIGNATURE
+            .lines()
+            .take(3)
+            .map(|line| format!("{line}\n"))
+            .collect();
+        assert!(verify(PUBLIC_KEY, &truncated, DATA).is_err());
+    }
+}
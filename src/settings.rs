@@ -0,0 +1,692 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Component, Path};
+
+use serde::Deserialize;
+use zed_extension_api::{
+    self as zed, LanguageServerId, Worktree,
+    serde_json::{self, Map, Value},
+    settings::LspSettings,
+};
+
+use crate::{SERVER_SUBCOMMAND, version};
+
+/// The `settings` object of the `rumdl` language server.
+///
+/// `binary`, `config` and `exclude` belong to the extension; every other key is
+/// forwarded to rumdl as workspace configuration.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct RumdlSettings {
+    pub binary: BinarySettings,
+    pub config: ConfigSettings,
+    /// Globs merged into the `global.exclude` of `config.inline`.
+    pub exclude: Vec<String>,
+    #[serde(flatten)]
+    server: Map<String, Value>,
+}
+
+/// Keys of rumdl's flat rule settings, see `did_change_configuration` in rumdl.
+const RULE_SETTING_KEYS: &[&str] = &["disable", "enable", "lineLength"];
+
+/// Fields of rumdl's structured `RumdlLspConfig`.
+const LSP_CONFIG_KEYS: &[&str] = &[
+    "configPath",
+    "enableLinting",
+    "enableAutoFix",
+    "enableRules",
+    "disableRules",
+    "configurationPreference",
+    "settings",
+    "enableLinkCompletions",
+    "enableLinkNavigation",
+    "enableSymbols",
+    "linkCompletionContentRoots",
+];
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct ConfigSettings {
+    /// TOML handed to the server through a generated `--config` file.
+    pub inline: Option<String>,
+    /// JSON or TOML file whose workspace configuration is merged beneath these
+    /// settings; `~` and `$VAR` are expanded.
+    pub settings_file: Option<String>,
+    /// Have rumdl ignore config files on disk and use only these settings.
+    pub no_config: bool,
+    /// The same as `no_config`, named after rumdl's `--isolated` alias.
+    pub isolated: bool,
+    #[serde(flatten)]
+    unknown: Map<String, Value>,
+}
+
+#[derive(Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct BinarySettings {
+    /// Whether to start the server at all for this worktree.
+    pub enabled: bool,
+    /// How many of the most recent `rumdl-<version>` directories survive cleanup.
+    pub keep_versions: usize,
+    /// Whether a `rumdl` found on the system is used before the managed install.
+    pub use_path: bool,
+    /// Whether to look for `rumdl` in well-known install locations missing from PATH.
+    pub probe_common_paths: bool,
+    /// Use whatever binary is resolved without version checks or network access.
+    pub trust_existing: bool,
+    /// Install exactly this rumdl version instead of the latest release.
+    pub version: Option<String>,
+    /// Install the release with this Git tag; takes precedence over `version`.
+    pub tag: Option<String>,
+    /// How much of the server's own logging ends up in the Zed log.
+    pub trace_server: TraceServer,
+    /// Launch the server with `NO_COLOR`; follows the shell's `NO_COLOR` when unset.
+    pub no_color: Option<bool>,
+    /// Download the release asset with exactly this name, skipping platform matching.
+    pub asset_name: Option<String>,
+    /// On aarch64 Linux, install the x86_64 build when there's no native one.
+    pub allow_x86_emulation: bool,
+    /// Replaces the OS component (e.g. `unknown-linux-gnu`) used to match asset names.
+    pub os_string: Option<String>,
+    /// Archive format picked first when a release ships several for the platform.
+    pub prefer_compression: Option<Compression>,
+    /// Base URLs serving `<tag>/<asset name>`, tried in order after GitHub.
+    pub mirrors: Vec<String>,
+    /// Download only from `mirrors`, never from GitHub.
+    pub mirrors_only: bool,
+    /// Path of the binary inside the extracted archive, relative to its root.
+    pub archive_member: Option<String>,
+    /// Refuse to use a downloaded binary whose SHA-256 digest differs from this.
+    pub expected_sha256: Option<String>,
+    /// Fail the install when the release's published checksum can't be checked.
+    pub require_checksum: bool,
+    /// Fail the install unless the release's minisign signature verifies.
+    pub verify_signature: bool,
+    /// The minisign public key signatures are checked against.
+    pub signature_public_key: Option<String>,
+    /// Whether checksum and signature fetches may follow redirects; Zed's own
+    /// downloads always do.
+    pub follow_redirects: bool,
+    /// Record install attempts in a size-capped local `install.log`.
+    pub install_log: bool,
+    /// The subcommand that starts rumdl's language server.
+    pub subcommand: String,
+    /// Arguments inserted before the `server` subcommand.
+    pub pre_args: Vec<String>,
+    /// Arguments appended after the `server` subcommand.
+    pub arguments: Vec<String>,
+    /// Command (argv) run with the downloaded binary's path appended; must exit 0.
+    pub verify_command: Option<Vec<String>>,
+    #[serde(flatten)]
+    unknown: Map<String, Value>,
+}
+
+/// Mirrors the LSP trace levels.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TraceServer {
+    #[default]
+    Off,
+    Messages,
+    Verbose,
+}
+
+impl TraceServer {
+    /// The `RUST_LOG` filter rumdl is launched with, if any.
+    pub const fn log_filter(self) -> Option<&'static str> {
+        match self {
+            Self::Off => None,
+            Self::Messages => Some("info"),
+            Self::Verbose => Some("debug"),
+        }
+    }
+}
+
+/// Archive formats a release may offer for the same platform.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Gz,
+    Xz,
+    Zip,
+}
+
+impl Compression {
+    /// Whether an entry of the platform's extension list is of this format.
+    pub fn matches(self, ext: &str) -> bool {
+        match self {
+            Self::Gz => matches!(ext, "tar.gz" | "tgz" | "gz"),
+            // Zed can't unpack xz, so it's never available and other formats
+            // are used instead.
+            Self::Xz => false,
+            Self::Zip => ext == "zip",
+        }
+    }
+}
+
+impl BinarySettings {
+    /// A hash of the settings that decide which managed binary is used, so
+    /// changes such as `trace_server` don't force a re-resolution.
+    pub fn resolution_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        (
+            self.trust_existing,
+            &self.version,
+            &self.tag,
+            &self.asset_name,
+            &self.os_string,
+            self.allow_x86_emulation,
+            self.prefer_compression,
+            &self.mirrors,
+            self.mirrors_only,
+            &self.archive_member,
+        )
+            .hash(&mut hasher);
+        (
+            &self.expected_sha256,
+            self.require_checksum,
+            self.verify_signature,
+            &self.signature_public_key,
+            &self.verify_command,
+        )
+            .hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+impl Default for BinarySettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            keep_versions: 1,
+            use_path: true,
+            probe_common_paths: true,
+            trust_existing: false,
+            version: None,
+            tag: None,
+            trace_server: TraceServer::default(),
+            no_color: None,
+            asset_name: None,
+            allow_x86_emulation: false,
+            os_string: None,
+            prefer_compression: None,
+            mirrors: Vec::new(),
+            mirrors_only: false,
+            archive_member: None,
+            expected_sha256: None,
+            require_checksum: false,
+            verify_signature: false,
+            signature_public_key: None,
+            follow_redirects: true,
+            verify_command: None,
+            install_log: false,
+            subcommand: SERVER_SUBCOMMAND.into(),
+            pre_args: Vec::new(),
+            arguments: Vec::new(),
+            unknown: Map::new(),
+        }
+    }
+}
+
+impl RumdlSettings {
+    pub fn for_worktree(
+        language_server_id: &LanguageServerId,
+        worktree: &Worktree,
+    ) -> zed::Result<Self> {
+        Self::from_raw(Self::raw_for_worktree(language_server_id, worktree))
+    }
+
+    /// The unparsed `settings` object, cheap to compare against an earlier one.
+    pub fn raw_for_worktree(
+        language_server_id: &LanguageServerId,
+        worktree: &Worktree,
+    ) -> Option<Value> {
+        LspSettings::for_worktree(language_server_id.as_ref(), worktree)
+            .ok()
+            .and_then(|lsp_settings| lsp_settings.settings)
+    }
+
+    pub fn from_raw(settings: Option<Value>) -> zed::Result<Self> {
+        let Some(settings) = settings else {
+            return Ok(Self::default());
+        };
+
+        let settings: Self =
+            serde_json::from_value(settings).map_err(|e| format!("Invalid rumdl settings: {e}"))?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Rejects values that would otherwise only fail much later, e.g. after a
+    /// round-trip to GitHub.
+    fn validate(&self) -> zed::Result<()> {
+        if let Some(version) = &self.binary.version
+            && version::parse_version(version).is_none()
+        {
+            return Err(format!(
+                "Invalid rumdl setting `binary.version`: `{version}` is not a semver version"
+            ));
+        }
+
+        if let Some(archive_member) = &self.binary.archive_member {
+            let path = Path::new(archive_member);
+            let is_relative = path
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)));
+            if archive_member.is_empty() || !is_relative {
+                return Err(format!(
+                    "Invalid rumdl setting `binary.archive_member`: `{archive_member}` must be a relative path inside the archive"
+                ));
+            }
+        }
+
+        if self.binary.verify_signature && self.binary.signature_public_key.is_none() {
+            return Err(
+                "Invalid rumdl setting `binary.verify_signature`: `binary.signature_public_key` is not set"
+                    .into(),
+            );
+        }
+
+        let subcommand = &self.binary.subcommand;
+        if subcommand.is_empty() || subcommand.contains(char::is_whitespace) {
+            return Err(format!(
+                "Invalid rumdl setting `binary.subcommand`: `{subcommand}` must be a single word"
+            ));
+        }
+
+        for (key, args) in [
+            ("binary.pre_args", &self.binary.pre_args),
+            ("binary.arguments", &self.binary.arguments),
+        ] {
+            if args.iter().any(|arg| arg == subcommand) {
+                return Err(format!(
+                    "Invalid rumdl setting `{key}`: `{subcommand}` is added automatically"
+                ));
+            }
+        }
+
+        for mirror in &self.binary.mirrors {
+            if !mirror.starts_with("https://") && !mirror.starts_with("http://") {
+                return Err(format!(
+                    "Invalid rumdl setting `binary.mirrors`: `{mirror}` is not an http(s) URL"
+                ));
+            }
+        }
+
+        if self.binary.mirrors_only && self.binary.mirrors.is_empty() {
+            return Err(
+                "Invalid rumdl setting `binary.mirrors_only`: `binary.mirrors` is empty".into(),
+            );
+        }
+
+        for pattern in &self.exclude {
+            glob::Pattern::new(pattern)
+                .map_err(|e| format!("Invalid rumdl setting `exclude`: `{pattern}`: {e}"))?;
+        }
+
+        Ok(())
+    }
+
+    /// Logs every key that doesn't map to a known setting, so typos don't go
+    /// unnoticed, and values that can't take effect.
+    pub fn warn_ignored_settings(&self) {
+        if self.binary.prefer_compression == Some(Compression::Xz) {
+            eprintln!(
+                "[rumdl] Zed can't extract xz archives, ignoring `binary.prefer_compression`"
+            );
+        }
+
+        let unknown_binary = self
+            .binary
+            .unknown
+            .keys()
+            .map(|key| format!("binary.{key}"));
+        let unknown_config = self
+            .config
+            .unknown
+            .keys()
+            .map(|key| format!("config.{key}"));
+        for key in unknown_binary.chain(unknown_config) {
+            eprintln!("[rumdl] ignoring unknown setting `{key}`");
+        }
+
+        // Forwarded anyway: rumdl may know keys newer than this list.
+        for key in self.server.keys() {
+            let is_known = key == "rumdl"
+                || RULE_SETTING_KEYS.contains(&key.as_str())
+                || LSP_CONFIG_KEYS.contains(&key.as_str())
+                || Self::is_rule_name(key);
+            if !is_known {
+                eprintln!("[rumdl] forwarding setting `{key}`, which rumdl doesn't define");
+            }
+        }
+    }
+
+    /// Whether `key` looks like a rule, by ID (`MD013`) or by alias (`line-length`).
+    fn is_rule_name(key: &str) -> bool {
+        let is_id = key
+            .get(..2)
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case("md"))
+            && key.len() > 2
+            && key[2..].bytes().all(|byte| byte.is_ascii_digit());
+        let is_alias = key.contains('-')
+            && key
+                .bytes()
+                .all(|byte| byte.is_ascii_lowercase() || byte.is_ascii_digit() || byte == b'-');
+        is_id || is_alias
+    }
+
+    /// `config.inline` with `exclude` merged into its `global.exclude`.
+    ///
+    /// rumdl only reads excludes from config files, never over LSP, so the
+    /// generated inline config is the one place the extension can add them.
+    pub fn inline_config(&self) -> zed::Result<Option<String>> {
+        let Some(inline) = &self.config.inline else {
+            return Ok(None);
+        };
+        if self.exclude.is_empty() {
+            return Ok(Some(inline.clone()));
+        }
+
+        let invalid = |reason: String| format!("Invalid rumdl setting `config.inline`: {reason}");
+        let mut config: toml::Table = toml::from_str(inline).map_err(|e| invalid(e.to_string()))?;
+        let exclude = config
+            .entry("global")
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+            .as_table_mut()
+            .ok_or_else(|| invalid("`global` is not a table".into()))?
+            .entry("exclude")
+            .or_insert_with(|| toml::Value::Array(Vec::new()))
+            .as_array_mut()
+            .ok_or_else(|| invalid("`global.exclude` is not an array".into()))?;
+
+        for pattern in &self.exclude {
+            let pattern = toml::Value::String(pattern.clone());
+            if !exclude.contains(&pattern) {
+                exclude.push(pattern);
+            }
+        }
+
+        toml::to_string(&config)
+            .map(Some)
+            .map_err(|e| invalid(e.to_string()))
+    }
+
+    /// The part of the settings rumdl itself understands.
+    pub fn server_configuration(&self) -> Option<Value> {
+        (!self.server.is_empty()).then(|| Value::Object(self.server.clone()))
+    }
+
+    /// Whether rumdl should use only the settings made in the editor.
+    pub fn ignores_config_files(&self) -> bool {
+        // An explicit config wins over settings that would make rumdl ignore it.
+        self.config.inline.is_none() && (self.config.no_config || self.config.isolated)
+    }
+
+    /// [`Self::server_configuration`] layered over the contents of
+    /// `config.settings_file`, so settings made in Zed win.
+    pub fn workspace_configuration(&self, settings_file: Option<Value>) -> Option<Value> {
+        let configuration = self.server_configuration();
+        let mut merged = match (settings_file, configuration) {
+            (Some(mut merged), Some(configuration)) => {
+                Self::merge_values(&mut merged, configuration);
+                merged
+            }
+            (Some(merged), None) | (None, Some(merged)) => merged,
+            (None, None) if self.ignores_config_files() => Value::Object(Map::new()),
+            (None, None) => return None,
+        };
+
+        if self.ignores_config_files()
+            && let Value::Object(configuration) = &mut merged
+        {
+            Self::prefer_editor_only(configuration);
+        }
+        Some(merged)
+    }
+
+    /// Sets rumdl's `configurationPreference` to `editorOnly`, which `rumdl
+    /// server --no-config` doesn't do.
+    ///
+    /// rumdl only honors the preference in its structured configuration and
+    /// when `settings` is present. Any flat rule setting at the top level makes
+    /// it read the flat form instead, so those all move under `settings`.
+    fn prefer_editor_only(configuration: &mut Map<String, Value>) {
+        if let Some(Value::Object(nested)) = configuration.get_mut("rumdl") {
+            return Self::prefer_editor_only(nested);
+        }
+
+        let rule_keys: Vec<_> = configuration
+            .keys()
+            .filter(|key| RULE_SETTING_KEYS.contains(&key.as_str()) || Self::is_rule_name(key))
+            .cloned()
+            .collect();
+        let rule_settings: Vec<_> = rule_keys
+            .iter()
+            .filter_map(|key| configuration.remove_entry(key))
+            .collect();
+
+        let settings = configuration
+            .entry("settings")
+            .or_insert_with(|| Value::Object(Map::new()));
+        if let Value::Object(settings) = settings {
+            for (key, value) in rule_settings {
+                settings.entry(key).or_insert(value);
+            }
+        }
+        configuration.insert("configurationPreference".into(), "editorOnly".into());
+    }
+
+    fn merge_values(base: &mut Value, overrides: Value) {
+        match (base, overrides) {
+            (Value::Object(base), Value::Object(overrides)) => {
+                for (key, value) in overrides {
+                    match base.get_mut(&key) {
+                        Some(existing) => Self::merge_values(existing, value),
+                        None => {
+                            base.insert(key, value);
+                        }
+                    }
+                }
+            }
+            (base, overrides) => *base = overrides,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zed_extension_api::serde_json::json;
+
+    fn settings(raw: Value) -> RumdlSettings {
+        RumdlSettings::from_raw(Some(raw)).unwrap()
+    }
+
+    fn configuration(raw: Value) -> Option<Value> {
+        settings(raw).workspace_configuration(None)
+    }
+
+    #[test]
+    fn forwards_everything_but_extension_keys() {
+        let configuration = configuration(json!({
+            "binary": { "keep_versions": 2 },
+            "config": { "no_config": false },
+            "exclude": ["CHANGELOG.md"],
+            "disable": ["MD009"],
+            "enableLinting": true,
+        }));
+        assert_eq!(
+            configuration,
+            Some(json!({ "disable": ["MD009"], "enableLinting": true }))
+        );
+    }
+
+    #[test]
+    fn editor_only_moves_flat_settings_under_settings() {
+        let configuration = configuration(json!({
+            "config": { "no_config": true },
+            "disable": ["MD009"],
+            "MD013": { "lineLength": 100 },
+        }));
+        assert_eq!(
+            configuration,
+            Some(json!({
+                "configurationPreference": "editorOnly",
+                "settings": { "disable": ["MD009"], "MD013": { "lineLength": 100 } },
+            }))
+        );
+    }
+
+    #[test]
+    fn editor_only_adds_settings_to_structured_configuration() {
+        let configuration = configuration(json!({
+            "config": { "isolated": true },
+            "enableLinting": false,
+        }));
+        assert_eq!(
+            configuration,
+            Some(json!({
+                "configurationPreference": "editorOnly",
+                "enableLinting": false,
+                "settings": {},
+            }))
+        );
+    }
+
+    #[test]
+    fn editor_only_moves_flat_settings_out_of_mixed_configuration() {
+        let configuration = configuration(json!({
+            "config": { "no_config": true },
+            "enableLinting": false,
+            "disable": ["MD009"],
+            "line-length": { "line_length": 100 },
+            "settings": { "disable": ["MD001"], "lineLength": 80 },
+        }));
+        assert_eq!(
+            configuration,
+            Some(json!({
+                "configurationPreference": "editorOnly",
+                "enableLinting": false,
+                "settings": {
+                    "disable": ["MD001"],
+                    "lineLength": 80,
+                    "line-length": { "line_length": 100 },
+                },
+            }))
+        );
+    }
+
+    #[test]
+    fn editor_only_applies_under_rumdl_key() {
+        let configuration = configuration(json!({
+            "config": { "no_config": true },
+            "rumdl": { "disable": ["MD009"] },
+        }));
+        assert_eq!(
+            configuration,
+            Some(json!({
+                "rumdl": {
+                    "configurationPreference": "editorOnly",
+                    "settings": { "disable": ["MD009"] },
+                },
+            }))
+        );
+    }
+
+    #[test]
+    fn editor_only_without_other_settings() {
+        let configuration = configuration(json!({ "config": { "no_config": true } }));
+        assert_eq!(
+            configuration,
+            Some(json!({ "configurationPreference": "editorOnly", "settings": {} }))
+        );
+        assert_eq!(RumdlSettings::default().workspace_configuration(None), None);
+    }
+
+    #[test]
+    fn inline_config_wins_over_no_config() {
+        let configuration = configuration(json!({
+            "config": { "inline": "[global]\n", "no_config": true },
+            "disable": ["MD009"],
+        }));
+        assert_eq!(configuration, Some(json!({ "disable": ["MD009"] })));
+    }
+
+    #[test]
+    fn settings_file_is_merged_beneath_settings() {
+        let settings = settings(json!({
+            "disable": ["MD009"],
+            "MD013": { "lineLength": 100 },
+        }));
+        let settings_file = json!({
+            "disable": ["MD001", "MD002"],
+            "enable": ["MD003"],
+            "MD013": { "lineLength": 80, "strict": true },
+        });
+        assert_eq!(
+            settings.workspace_configuration(Some(settings_file)),
+            Some(json!({
+                "disable": ["MD009"],
+                "enable": ["MD003"],
+                "MD013": { "lineLength": 100, "strict": true },
+            }))
+        );
+    }
+
+    #[test]
+    fn merges_exclude_into_inline_config() {
+        let settings = settings(json!({
+            "config": { "inline": "[global]\nline-length = 100\nexclude = [\"a.md\"]\n" },
+            "exclude": ["a.md", "node_modules/**"],
+        }));
+        let inline: toml::Table =
+            toml::from_str(&settings.inline_config().unwrap().unwrap()).unwrap();
+        assert_eq!(
+            inline["global"]["exclude"],
+            toml::Value::Array(vec!["a.md".into(), "node_modules/**".into()])
+        );
+        assert_eq!(inline["global"]["line-length"], toml::Value::Integer(100));
+    }
+
+    #[test]
+    fn adds_global_table_to_inline_config() {
+        let settings = settings(json!({
+            "config": { "inline": "[MD013]\nline-length = 100\n" },
+            "exclude": ["CHANGELOG.md"],
+        }));
+        let inline: toml::Table =
+            toml::from_str(&settings.inline_config().unwrap().unwrap()).unwrap();
+        assert_eq!(
+            inline["global"]["exclude"],
+            toml::Value::Array(vec!["CHANGELOG.md".into()])
+        );
+    }
+
+    #[test]
+    fn keeps_inline_config_without_exclude() {
+        let inline = "[global]\nline-length = 100 # kept as written\n";
+        let settings = settings(json!({ "config": { "inline": inline } }));
+        assert_eq!(settings.inline_config(), Ok(Some(inline.into())));
+        assert_eq!(RumdlSettings::default().inline_config(), Ok(None));
+    }
+
+    #[test]
+    fn rejects_exclude_for_malformed_inline_config() {
+        for inline in ["global = 1\n", "[global]\nexclude = \"a.md\"\n", "[global"] {
+            let settings = settings(json!({
+                "config": { "inline": inline },
+                "exclude": ["CHANGELOG.md"],
+            }));
+            assert!(settings.inline_config().is_err(), "{inline}");
+        }
+    }
+
+    #[test]
+    fn recognizes_rule_names() {
+        for key in ["MD013", "md013", "line-length", "no-trailing-spaces"] {
+            assert!(RumdlSettings::is_rule_name(key), "{key}");
+        }
+        for key in ["MD", "MD01a", "rules", "line_length", "lineLength"] {
+            assert!(!RumdlSettings::is_rule_name(key), "{key}");
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use semver::Version;
+
+/// Parses a rumdl version or release tag such as `0.0.40` or `v0.0.40`.
+///
+/// Abbreviated tags are padded, so `1` and `0.1` parse as `1.0.0` and `0.1.0`.
+pub fn parse_version(version: &str) -> Option<Version> {
+    let version = version.strip_prefix('v').unwrap_or(version);
+    let core_end = version.find(['-', '+']).unwrap_or(version.len());
+    let (core, suffix) = version.split_at(core_end);
+
+    let padding = match core.matches('.').count() {
+        0 => ".0.0",
+        1 => ".0",
+        _ => "",
+    };
+    Version::parse(&format!("{core}{padding}{suffix}")).ok()
+}
+
+/// Encodes a version for use in a directory name.
+///
+/// Pre-release (`-`) and build metadata (`+`) separators are awkward on some
+/// filesystems. Neither `_` nor `~` can appear in a semver version, so the
+/// encoding can be undone with [`from_dir_name`].
+pub fn to_dir_name(version: &str) -> String {
+    version.replace('-', "_").replace('+', "~")
+}
+
+/// Reverses [`to_dir_name`].
+pub fn from_dir_name(dir_name: &str) -> String {
+    dir_name.replace('_', "-").replace('~', "+")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pads_abbreviated_versions() {
+        assert_eq!(parse_version("1"), Some(Version::new(1, 0, 0)));
+        assert_eq!(parse_version("0.1"), Some(Version::new(0, 1, 0)));
+        assert_eq!(parse_version("0.1.0"), Some(Version::new(0, 1, 0)));
+        assert_eq!(parse_version("v0.1"), parse_version("0.1.0"));
+    }
+
+    #[test]
+    fn rejects_non_versions() {
+        assert_eq!(parse_version(""), None);
+        assert_eq!(parse_version("latest"), None);
+        assert_eq!(parse_version("0.1.0.0"), None);
+    }
+
+    #[test]
+    fn parses_pre_release_and_build_metadata() {
+        let version = parse_version("v0.0.40-rc.1").unwrap();
+        assert_eq!(version.pre.as_str(), "rc.1");
+        assert!(version < Version::new(0, 0, 40));
+
+        let version = parse_version("0.0.40+build.3").unwrap();
+        assert_eq!(version.build.as_str(), "build.3");
+        assert_eq!(
+            version.cmp_precedence(&Version::new(0, 0, 40)),
+            std::cmp::Ordering::Equal
+        );
+
+        let version = parse_version("0.1-rc.1+build.3").unwrap();
+        assert_eq!(version.to_string(), "0.1.0-rc.1+build.3");
+    }
+
+    #[test]
+    fn dir_names_have_no_separators() {
+        assert_eq!(to_dir_name("0.0.40"), "0.0.40");
+        assert_eq!(to_dir_name("0.0.40-rc.1"), "0.0.40_rc.1");
+        assert_eq!(to_dir_name("0.0.40+build.3"), "0.0.40~build.3");
+        assert_eq!(to_dir_name("0.0.40-rc.1+build-3"), "0.0.40_rc.1~build_3");
+    }
+
+    #[test]
+    fn dir_names_round_trip() {
+        for version in [
+            "0.0.40",
+            "0.0.40-rc.1",
+            "0.0.40+build.3",
+            "1.0.0-rc--1",
+            "1.0.0-rc+1",
+            "1.0.0-x-y+build--3",
+        ] {
+            assert_eq!(from_dir_name(&to_dir_name(version)), version);
+        }
+        assert_ne!(to_dir_name("1.0.0-rc--1"), to_dir_name("1.0.0-rc+1"));
+    }
+}